@@ -0,0 +1,120 @@
+use chrono::Utc;
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::store::Collection;
+use crate::todo::Todo;
+
+/// A line of a todo's checklist: lighter than a subtask, with no dates,
+/// tags or notes of its own.
+#[derive(GraphQLObject, Clone, Debug, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    /// Unique within its todo.
+    pub id: String,
+    pub text: String,
+    pub checked: bool,
+    /// Fractional index within the checklist, like `Todo.position`.
+    pub position: String,
+}
+
+impl ChecklistItem {
+    pub fn new(text: String, position: String) -> Self {
+        ChecklistItem {
+            id: Uuid::new_v4().to_string(),
+            text,
+            checked: false,
+            position,
+        }
+    }
+}
+
+/// Applies `f` to the checklist of todo `todo_id` and the index of item
+/// `item_id` in it, within a single store update so the item is looked up in
+/// the version of the todo being written. Nothing is written, and `updatedAt`
+/// is left alone, if the item doesn't exist or `f` fails.
+pub fn update_item(
+    store: &dyn Collection<Todo>,
+    todo_id: &str,
+    item_id: &str,
+    mut f: impl FnMut(&mut Vec<ChecklistItem>, usize) -> AppResult<()>,
+) -> AppResult<Todo> {
+    let mut result = Ok(());
+    let todo = store
+        .update_if(todo_id, &mut |t| {
+            result = match t.checklist.iter().position(|i| i.id == item_id) {
+                Some(index) => f(&mut t.checklist, index),
+                None => Err(AppError::not_found("checklist item", item_id)),
+            };
+            if result.is_ok() {
+                t.updated_at = Utc::now();
+            }
+            result.is_ok()
+        })?
+        .ok_or_else(|| AppError::not_found("todo", todo_id))?;
+    result.map(|()| todo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{MemoryStore, TodoStore};
+
+    fn todo_with_item() -> (MemoryStore, Todo, String) {
+        let mut todo = Todo::new("pack".into());
+        let item = ChecklistItem::new("socks".into(), "a0".into());
+        let item_id = item.id.clone();
+        todo.checklist.push(item);
+        todo.updated_at = "2025-01-01T00:00:00Z".parse().unwrap();
+        (MemoryStore::new(vec![todo.clone()]), todo, item_id)
+    }
+
+    #[test]
+    fn updates_an_existing_item() {
+        let (store, todo, item_id) = todo_with_item();
+        let updated = update_item(store.todos(), &todo.id, &item_id, |items, i| {
+            items[i].checked = true;
+            Ok(())
+        })
+        .unwrap();
+        assert!(updated.checklist[0].checked);
+        assert!(updated.updated_at > todo.updated_at);
+    }
+
+    #[test]
+    fn missing_item_is_not_found_and_writes_nothing() {
+        let (store, todo, _) = todo_with_item();
+        let err = update_item(store.todos(), &todo.id, "gone", |_, _| Ok(())).unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::NotFound {
+                kind: "checklist item",
+                ..
+            }
+        ));
+        let stored = store.todos().get(&todo.id).unwrap().unwrap();
+        assert_eq!(stored.updated_at, todo.updated_at);
+    }
+
+    #[test]
+    fn failing_update_writes_nothing() {
+        let (store, todo, item_id) = todo_with_item();
+        let err = update_item(store.todos(), &todo.id, &item_id, |items, i| {
+            items.remove(i);
+            Err(AppError::invalid("beforeId", "nope"))
+        })
+        .unwrap_err();
+        assert!(matches!(err, AppError::Invalid(_)));
+        let stored = store.todos().get(&todo.id).unwrap().unwrap();
+        assert_eq!(stored.checklist.len(), 1);
+        assert_eq!(stored.updated_at, todo.updated_at);
+    }
+
+    #[test]
+    fn missing_todo_is_not_found() {
+        let (store, _, item_id) = todo_with_item();
+        let err = update_item(store.todos(), "gone", &item_id, |_, _| Ok(())).unwrap_err();
+        assert!(matches!(err, AppError::NotFound { kind: "todo", .. }));
+    }
+}
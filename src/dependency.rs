@@ -0,0 +1,189 @@
+//! "B can't start until A is done" links between todos. Each todo stores the
+//! ids it depends on; the reverse direction and the ordering are derived.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::todo::Todo;
+
+/// Todos that `todo` depends on, in the order the dependencies were added.
+pub fn blocked_by<'a>(todos: &'a [Todo], todo: &Todo) -> Vec<&'a Todo> {
+    todo.depends_on
+        .iter()
+        .filter_map(|id| todos.iter().find(|t| &t.id == id))
+        .collect()
+}
+
+/// Todos that depend on `id`, in store order.
+pub fn blocks<'a>(todos: &'a [Todo], id: &str) -> Vec<&'a Todo> {
+    todos
+        .iter()
+        .filter(|t| t.depends_on.iter().any(|d| d == id))
+        .collect()
+}
+
+/// Shortest chain of dependencies leading from `from` to `to`, both included,
+/// if `from` depends on `to` directly or transitively.
+pub fn path<'a>(todos: &'a [Todo], from: &str, to: &str) -> Option<Vec<&'a Todo>> {
+    let by_id: HashMap<&str, &Todo> = todos.iter().map(|t| (t.id.as_str(), t)).collect();
+    let start = *by_id.get(from)?;
+    // Breadth-first, remembering how each todo was reached.
+    let mut reached_from: HashMap<&str, Option<&str>> = HashMap::from([(from, None)]);
+    let mut queue = vec![start];
+    let mut next = 0;
+    while let Some(todo) = queue.get(next).copied() {
+        next += 1;
+        if todo.id == to {
+            let mut chain = vec![todo];
+            let mut id = todo.id.as_str();
+            while let Some(Some(prev)) = reached_from.get(id) {
+                chain.push(by_id[prev]);
+                id = prev;
+            }
+            chain.reverse();
+            return Some(chain);
+        }
+        for dep in &todo.depends_on {
+            if let Some(dep) = by_id.get(dep.as_str())
+                && !reached_from.contains_key(dep.id.as_str())
+            {
+                reached_from.insert(&dep.id, Some(&todo.id));
+                queue.push(dep);
+            }
+        }
+    }
+    None
+}
+
+/// Describes the cycle a new dependency would close, given the [`path`]
+/// from the todo to be depended on back to the todo depending on it. The
+/// cycle is spelled out starting and ending at the depending todo.
+pub fn cycle_message(chain: &[&Todo]) -> String {
+    let titles: Vec<String> = chain[chain.len() - 1..]
+        .iter()
+        .chain(chain)
+        .map(|t| format!("{:?}", t.title))
+        .collect();
+    format!(
+        "would create a dependency cycle: {} (each depends on the next)",
+        titles.join(" -> ")
+    )
+}
+
+/// Reorders `todos` so every todo comes after the todos it depends on.
+/// Among todos whose dependencies are all placed, the earliest in the input
+/// goes first, so the input order breaks ties. Dependencies outside `todos`
+/// are ignored.
+pub fn topological(todos: Vec<Todo>) -> Vec<Todo> {
+    let index: HashMap<&str, usize> = todos
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.id.as_str(), i))
+        .collect();
+    let mut pending = vec![0; todos.len()];
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); todos.len()];
+    for (i, todo) in todos.iter().enumerate() {
+        for dep in &todo.depends_on {
+            if let Some(&d) = index.get(dep.as_str()) {
+                pending[i] += 1;
+                dependents[d].push(i);
+            }
+        }
+    }
+
+    let mut ready: BTreeSet<usize> = (0..todos.len()).filter(|&i| pending[i] == 0).collect();
+    let mut order = Vec::with_capacity(todos.len());
+    while let Some(i) = ready.pop_first() {
+        order.push(i);
+        for &j in &dependents[i] {
+            pending[j] -= 1;
+            if pending[j] == 0 {
+                ready.insert(j);
+            }
+        }
+    }
+    // Mutations reject cycles, so every todo is placed; should stored data
+    // hold one anyway, append the stragglers rather than drop them.
+    order.extend((0..todos.len()).filter(|&i| pending[i] > 0));
+
+    let mut slots: Vec<Option<Todo>> = todos.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|i| slots[i].take()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(id, depends on)` pairs; titles are the ids.
+    fn todos(links: &[(&str, &[&str])]) -> Vec<Todo> {
+        links
+            .iter()
+            .map(|&(id, deps)| {
+                let mut todo = Todo::new(id.into());
+                todo.id = id.into();
+                todo.depends_on = deps.iter().map(|&d| d.into()).collect();
+                todo
+            })
+            .collect()
+    }
+
+    fn ids<'a>(todos: impl IntoIterator<Item = &'a Todo>) -> Vec<&'a str> {
+        todos.into_iter().map(|t| t.id.as_str()).collect()
+    }
+
+    #[test]
+    fn path_finds_the_shortest_chain() {
+        let todos = todos(&[
+            ("a", &["b", "c"]),
+            ("b", &["d"]),
+            ("c", &["e"]),
+            ("d", &["e"]),
+            ("e", &[]),
+        ]);
+        assert_eq!(ids(path(&todos, "a", "e").unwrap()), ["a", "c", "e"]);
+        assert_eq!(ids(path(&todos, "b", "b").unwrap()), ["b"]);
+        assert!(path(&todos, "e", "a").is_none());
+        assert!(path(&todos, "missing", "a").is_none());
+    }
+
+    #[test]
+    fn describes_a_direct_cycle() {
+        // Adding "b depends on a" while a depends on b.
+        let todos = todos(&[("a", &["b"]), ("b", &[])]);
+        let chain = path(&todos, "a", "b").unwrap();
+        assert_eq!(
+            cycle_message(&chain),
+            r#"would create a dependency cycle: "b" -> "a" -> "b" (each depends on the next)"#
+        );
+    }
+
+    #[test]
+    fn describes_a_transitive_cycle() {
+        // Adding "c depends on a" while a depends on b, which depends on c.
+        let todos = todos(&[("a", &["b"]), ("b", &["c"]), ("c", &[])]);
+        let chain = path(&todos, "a", "c").unwrap();
+        assert_eq!(
+            cycle_message(&chain),
+            r#"would create a dependency cycle: "c" -> "a" -> "b" -> "c" (each depends on the next)"#
+        );
+    }
+
+    #[test]
+    fn topological_places_dependencies_first_and_keeps_input_order_otherwise() {
+        let todos = todos(&[
+            ("report", &["data", "outline"]),
+            ("coffee", &[]),
+            ("outline", &[]),
+            ("data", &["outline", "elsewhere"]),
+        ]);
+        assert_eq!(
+            ids(&topological(todos)),
+            ["coffee", "outline", "data", "report"]
+        );
+    }
+
+    #[test]
+    fn topological_appends_todos_stuck_in_a_cycle() {
+        let todos = todos(&[("a", &["b"]), ("free", &[]), ("b", &["a"]), ("c", &["a"])]);
+        assert_eq!(ids(&topological(todos)), ["free", "a", "b", "c"]);
+    }
+}
@@ -0,0 +1,89 @@
+use juniper::{FieldError, IntoFieldError, Value, graphql_value};
+use std::fmt;
+
+use crate::store::StoreError;
+use crate::validation::Violation;
+
+/// Errors returned by resolvers. Each variant maps to a stable `code` in the
+/// GraphQL error `extensions`, so clients can branch on it instead of parsing
+/// messages.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound { kind: &'static str, id: String },
+    Invalid(Vec<Violation>),
+    Store(StoreError),
+}
+
+impl AppError {
+    pub fn not_found(kind: &'static str, id: impl Into<String>) -> Self {
+        AppError::NotFound {
+            kind,
+            id: id.into(),
+        }
+    }
+
+    /// A validation failure with a single violation.
+    pub fn invalid(field: &str, message: impl Into<String>) -> Self {
+        AppError::Invalid(vec![Violation {
+            field: field.to_owned(),
+            message: message.into(),
+        }])
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "NOT_FOUND",
+            AppError::Invalid(_) => "VALIDATION_FAILED",
+            AppError::Store(_) => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound { kind, id } => write!(f, "{kind} {id:?} not found"),
+            AppError::Invalid(violations) => {
+                let violations: Vec<_> = violations
+                    .iter()
+                    .map(|v| format!("{}: {}", v.field, v.message))
+                    .collect();
+                write!(f, "invalid input: {}", violations.join("; "))
+            }
+            AppError::Store(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<StoreError> for AppError {
+    fn from(e: StoreError) -> Self {
+        AppError::Store(e)
+    }
+}
+
+impl<S: juniper::ScalarValue> IntoFieldError<S> for AppError {
+    fn into_field_error(self) -> FieldError<S> {
+        let code = self.code();
+        let extensions = match &self {
+            AppError::NotFound { id, .. } => graphql_value!({ "code": code, "id": (id.as_str()) }),
+            AppError::Invalid(violations) => {
+                let violations = Value::list(
+                    violations
+                        .iter()
+                        .map(|v| {
+                            graphql_value!({
+                                "field": (v.field.as_str()),
+                                "message": (v.message.as_str()),
+                            })
+                        })
+                        .collect(),
+                );
+                graphql_value!({ "code": code, "violations": violations })
+            }
+            AppError::Store(_) => graphql_value!({ "code": code }),
+        };
+        FieldError::new(self, extensions)
+    }
+}
+
+pub type AppResult<T> = Result<T, AppError>;
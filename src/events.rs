@@ -0,0 +1,465 @@
+//! Change notifications for todos, fanned out to subscribers over a
+//! broadcast channel.
+//!
+//! Every change gets a sequence number, and the most recent ones are kept in
+//! a bounded buffer so a subscriber can resume after the last change it saw.
+//! Sequence numbers restart with the process, so ids handed to clients carry
+//! a per-boot epoch as well.
+
+use futures::stream::{self, BoxStream, StreamExt};
+use juniper::{GraphQLEnum, GraphQLObject};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{broadcast, watch};
+use uuid::Uuid;
+
+use crate::Context;
+use crate::list::TodoList;
+use crate::store::{Collection, StoreResult, TodoStore};
+use crate::tag::Tag;
+use crate::todo::Todo;
+
+/// How many changes a subscriber may fall behind before it has to catch up
+/// from the buffer.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(GraphQLEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// One todo that was created, updated or deleted.
+#[derive(GraphQLObject, Clone, Debug)]
+#[graphql(context = Context)]
+pub struct TodoChange {
+    pub kind: ChangeKind,
+    /// Id of the todo, also set for deletions.
+    pub id: String,
+    /// The todo after the change; `null` for deletions.
+    pub todo: Option<Todo>,
+}
+
+type Entry = (u64, TodoChange);
+
+struct Feed {
+    next_seq: u64,
+    buffer: VecDeque<Entry>,
+    capacity: usize,
+}
+
+impl Feed {
+    /// Buffered changes after `seq`, oldest first.
+    fn since(&self, seq: u64) -> VecDeque<Entry> {
+        self.buffer
+            .iter()
+            .filter(|(s, _)| *s > seq)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Sending half of the change feed; cheap to clone.
+#[derive(Clone)]
+pub struct Events {
+    feed: Arc<Mutex<Feed>>,
+    sender: broadcast::Sender<Entry>,
+    /// Random per boot; tells ids from this process apart from earlier ones.
+    epoch: u32,
+    /// Signalled after every write to any collection.
+    writes: Arc<watch::Sender<()>>,
+}
+
+impl Events {
+    /// Keeps the last `buffer_size` changes for resuming subscribers.
+    pub fn new(buffer_size: usize) -> Self {
+        Events {
+            feed: Arc::new(Mutex::new(Feed {
+                next_seq: 1,
+                buffer: VecDeque::with_capacity(buffer_size),
+                capacity: buffer_size,
+            })),
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            epoch: Uuid::new_v4().as_u128() as u32,
+            writes: Arc::new(watch::channel(()).0),
+        }
+    }
+
+    fn publish(&self, change: TodoChange) {
+        let mut feed = self.feed.lock();
+        let seq = feed.next_seq;
+        feed.next_seq += 1;
+        if feed.capacity > 0 {
+            if feed.buffer.len() == feed.capacity {
+                feed.buffer.pop_front();
+            }
+            feed.buffer.push_back((seq, change.clone()));
+        }
+        // Sending only fails when nobody is subscribed, which is fine.
+        let _ = self.sender.send((seq, change));
+        drop(feed);
+        self.touch();
+    }
+
+    fn touch(&self) {
+        self.writes.send_replace(());
+    }
+
+    /// Wakes up after writes to todos, tags or lists; unlike
+    /// [`Events::subscribe`], this also covers records that aren't todos.
+    /// Writes in quick succession may wake it up only once.
+    pub fn writes(&self) -> watch::Receiver<()> {
+        self.writes.subscribe()
+    }
+
+    /// Sequence number of the latest change, 0 before the first one.
+    pub fn head(&self) -> u64 {
+        self.feed.lock().next_seq - 1
+    }
+
+    /// Id for clients to resume after change `seq` with, see
+    /// [`Events::resume_from`].
+    pub fn event_id(&self, seq: u64) -> String {
+        format!("{:08x}-{seq}", self.epoch)
+    }
+
+    /// Sequence number to resume after for a client that last saw
+    /// `event_id`. Ids from an earlier boot, malformed ones and ones past the
+    /// latest change start from the latest change instead, since the changes
+    /// they refer to are gone.
+    pub fn resume_from(&self, event_id: Option<&str>) -> u64 {
+        let head = self.head();
+        event_id
+            .and_then(|id| id.split_once('-'))
+            .filter(|(epoch, _)| u32::from_str_radix(epoch, 16) == Ok(self.epoch))
+            .and_then(|(_, seq)| seq.parse().ok())
+            .filter(|&seq| seq <= head)
+            .unwrap_or(head)
+    }
+
+    /// Changes after the one `cursor` points at, or published from now on
+    /// without a cursor. The cursor is advanced as changes are taken from the
+    /// stream. Changes that already left the buffer are skipped, as are
+    /// changes a subscriber misses by lagging behind both the buffer and the
+    /// channel.
+    pub fn subscribe(&self, cursor: Option<Cursor>) -> BoxStream<'static, TodoChange> {
+        // Subscribe while holding the feed lock so no change falls between
+        // the backlog and the live channel.
+        let feed = self.feed.lock();
+        let receiver = self.sender.subscribe();
+        let last = match &cursor {
+            Some(cursor) => cursor.get(),
+            None => feed.next_seq - 1,
+        };
+        let subscriber = Subscriber {
+            backlog: feed.since(last),
+            receiver,
+            last,
+            feed: self.feed.clone(),
+            cursor,
+        };
+        drop(feed);
+        stream::unfold(subscriber, |mut s| async move {
+            let change = s.next().await?;
+            Some((change, s))
+        })
+        .boxed()
+    }
+}
+
+struct Subscriber {
+    backlog: VecDeque<Entry>,
+    receiver: broadcast::Receiver<Entry>,
+    last: u64,
+    feed: Arc<Mutex<Feed>>,
+    cursor: Option<Cursor>,
+}
+
+impl Subscriber {
+    async fn next(&mut self) -> Option<TodoChange> {
+        loop {
+            let (seq, change) = match self.backlog.pop_front() {
+                Some(entry) => entry,
+                None => match self.receiver.recv().await {
+                    Ok(entry) => entry,
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        self.backlog = self.feed.lock().since(self.last);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            };
+            // The live channel repeats what was already taken from the buffer.
+            if seq <= self.last {
+                continue;
+            }
+            self.last = seq;
+            if let Some(cursor) = &self.cursor {
+                cursor.set(seq);
+            }
+            return Some(change);
+        }
+    }
+}
+
+/// Sequence number of the last change a subscription has taken. Shared
+/// between the subscription and the transport sending its results, which uses
+/// it as the event id to resume from.
+#[derive(Clone)]
+pub struct Cursor(Arc<AtomicU64>);
+
+impl Cursor {
+    pub fn new(seq: u64) -> Self {
+        Cursor(Arc::new(AtomicU64::new(seq)))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn set(&self, seq: u64) {
+        self.0.store(seq, Ordering::SeqCst);
+    }
+}
+
+/// Wraps a store and publishes every successful write to its todos, so
+/// subscribers see changes from all mutations, including side effects such
+/// as subtasks removed by a cascading delete. Writes to tags and lists are
+/// only signalled through [`Events::writes`].
+pub struct PublishingStore {
+    inner: Arc<dyn TodoStore>,
+    events: Events,
+    tags: Touching<Tag>,
+    lists: Touching<TodoList>,
+    /// Held across each write and its publish, so changes are published in
+    /// the order they were applied.
+    writes: Mutex<()>,
+}
+
+impl PublishingStore {
+    pub fn new(inner: Arc<dyn TodoStore>, events: Events) -> Self {
+        PublishingStore {
+            tags: Touching {
+                inner: inner.clone(),
+                collection: |store| store.tags(),
+                events: events.clone(),
+            },
+            lists: Touching {
+                inner: inner.clone(),
+                collection: |store| store.lists(),
+                events: events.clone(),
+            },
+            inner,
+            events,
+            writes: Mutex::new(()),
+        }
+    }
+}
+
+impl Collection<Todo> for PublishingStore {
+    fn get(&self, id: &str) -> StoreResult<Option<Todo>> {
+        self.inner.todos().get(id)
+    }
+
+    fn list(&self) -> StoreResult<Vec<Todo>> {
+        self.inner.todos().list()
+    }
+
+    fn insert(&self, record: Todo) -> StoreResult<()> {
+        let change = TodoChange {
+            kind: ChangeKind::Created,
+            id: record.id.clone(),
+            todo: Some(record.clone()),
+        };
+        let _writes = self.writes.lock();
+        self.inner.todos().insert(record)?;
+        self.events.publish(change);
+        Ok(())
+    }
+
+    fn update_if(
+        &self,
+        id: &str,
+        f: &mut dyn FnMut(&mut Todo) -> bool,
+    ) -> StoreResult<Option<Todo>> {
+        let mut written = false;
+        let _writes = self.writes.lock();
+        let updated = self.inner.todos().update_if(id, &mut |t| {
+            written = f(t);
+            written
+        })?;
+        if let Some(todo) = updated.as_ref().filter(|_| written) {
+            self.events.publish(TodoChange {
+                kind: ChangeKind::Updated,
+                id: todo.id.clone(),
+                todo: Some(todo.clone()),
+            });
+        }
+        Ok(updated)
+    }
+
+    fn delete(&self, id: &str) -> StoreResult<bool> {
+        let _writes = self.writes.lock();
+        let deleted = self.inner.todos().delete(id)?;
+        if deleted {
+            self.events.publish(TodoChange {
+                kind: ChangeKind::Deleted,
+                id: id.to_owned(),
+                todo: None,
+            });
+        }
+        Ok(deleted)
+    }
+}
+
+impl TodoStore for PublishingStore {
+    fn todos(&self) -> &dyn Collection<Todo> {
+        self
+    }
+
+    fn tags(&self) -> &dyn Collection<Tag> {
+        &self.tags
+    }
+
+    fn lists(&self) -> &dyn Collection<TodoList> {
+        &self.lists
+    }
+
+    fn flush(&self) -> StoreResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// One of the inner store's other collections, signalling
+/// [`Events::writes`] after each successful write.
+struct Touching<T> {
+    inner: Arc<dyn TodoStore>,
+    collection: fn(&dyn TodoStore) -> &dyn Collection<T>,
+    events: Events,
+}
+
+impl<T> Touching<T> {
+    fn collection(&self) -> &dyn Collection<T> {
+        (self.collection)(self.inner.as_ref())
+    }
+}
+
+impl<T> Collection<T> for Touching<T> {
+    fn get(&self, id: &str) -> StoreResult<Option<T>> {
+        self.collection().get(id)
+    }
+
+    fn list(&self) -> StoreResult<Vec<T>> {
+        self.collection().list()
+    }
+
+    fn insert(&self, record: T) -> StoreResult<()> {
+        self.collection().insert(record)?;
+        self.events.touch();
+        Ok(())
+    }
+
+    fn update_if(&self, id: &str, f: &mut dyn FnMut(&mut T) -> bool) -> StoreResult<Option<T>> {
+        let mut written = false;
+        let updated = self.collection().update_if(id, &mut |r| {
+            written = f(r);
+            written
+        })?;
+        if updated.is_some() && written {
+            self.events.touch();
+        }
+        Ok(updated)
+    }
+
+    fn delete(&self, id: &str) -> StoreResult<bool> {
+        let deleted = self.collection().delete(id)?;
+        if deleted {
+            self.events.touch();
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn publish(events: &Events, n: usize) {
+        for _ in 0..n {
+            events.publish(TodoChange {
+                kind: ChangeKind::Created,
+                id: "x".into(),
+                todo: None,
+            });
+        }
+    }
+
+    #[test]
+    fn resumes_from_ids_of_this_boot() {
+        let events = Events::new(10);
+        publish(&events, 5);
+        assert_eq!(events.resume_from(Some(&events.event_id(3))), 3);
+        assert_eq!(events.resume_from(Some(&events.event_id(0))), 0);
+        assert_eq!(events.resume_from(None), 5);
+    }
+
+    #[test]
+    fn restarts_from_head_for_ids_it_did_not_hand_out() {
+        let events = Events::new(10);
+        publish(&events, 5);
+        let earlier_boot = Events::new(10);
+        for id in [
+            earlier_boot.event_id(3),
+            events.event_id(6),
+            "3".to_owned(),
+            format!("{:08x}-x", events.epoch),
+            "garbage".to_owned(),
+        ] {
+            assert_eq!(events.resume_from(Some(&id)), 5, "{id}");
+        }
+    }
+
+    #[test]
+    fn publishes_changes_in_the_order_they_were_applied() {
+        let events = Events::new(10_000);
+        let store = Arc::new(PublishingStore::new(
+            Arc::new(MemoryStore::default()),
+            events.clone(),
+        ));
+        let todo = Todo::new("0".into());
+        store.insert(todo.clone()).unwrap();
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                let id = todo.id.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        store
+                            .update(&id, &mut |t| {
+                                t.title = (t.title.parse::<u32>().unwrap() + 1).to_string()
+                            })
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        let changes: Vec<TodoChange> = futures::executor::block_on(
+            events
+                .subscribe(Some(Cursor::new(0)))
+                .take(events.head() as usize)
+                .collect(),
+        );
+        let titles: Vec<u32> = changes
+            .iter()
+            .map(|c| c.todo.as_ref().unwrap().title.parse().unwrap())
+            .collect();
+        assert_eq!(titles, (0..=1600).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,346 @@
+//! Server-side filtering and sorting arguments for the todo list queries.
+
+use chrono::{DateTime, Utc};
+use juniper::{GraphQLEnum, GraphQLInputObject};
+use std::cmp::Ordering;
+
+use crate::todo::{Priority, Todo};
+
+/// All given conditions must match.
+#[derive(GraphQLInputObject, Default)]
+pub struct TodoFilter {
+    pub completed: Option<bool>,
+    /// Case-insensitive substring match on the title.
+    pub title_contains: Option<String>,
+    /// Case-insensitive substring match on the title or the notes.
+    pub search: Option<String>,
+    /// Only todos created strictly before this instant.
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only todos created strictly after this instant.
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only todos with one of these priorities.
+    pub priority_in: Option<Vec<Priority>>,
+    /// Only todos with at least this priority.
+    pub min_priority: Option<Priority>,
+    /// Only todos carrying all of these tags.
+    pub tag_ids: Option<Vec<String>>,
+    /// Only todos in this list.
+    pub list_id: Option<String>,
+    /// Only top-level todos (`true`) or only subtasks (`false`).
+    pub top_level: Option<bool>,
+}
+
+impl TodoFilter {
+    pub fn matches(&self, todo: &Todo) -> bool {
+        if self.completed.is_some_and(|c| c != todo.completed) {
+            return false;
+        }
+        if let Some(needle) = &self.title_contains
+            && !todo.title.to_lowercase().contains(&needle.to_lowercase())
+        {
+            return false;
+        }
+        if let Some(needle) = &self.search {
+            let needle = needle.to_lowercase();
+            let in_notes = todo
+                .notes
+                .as_ref()
+                .is_some_and(|n| n.to_lowercase().contains(&needle));
+            if !in_notes && !todo.title.to_lowercase().contains(&needle) {
+                return false;
+            }
+        }
+        if self.created_before.is_some_and(|t| todo.created_at >= t) {
+            return false;
+        }
+        if self.created_after.is_some_and(|t| todo.created_at <= t) {
+            return false;
+        }
+        if let Some(priorities) = &self.priority_in
+            && !priorities.contains(&todo.priority)
+        {
+            return false;
+        }
+        if self.min_priority.is_some_and(|p| todo.priority < p) {
+            return false;
+        }
+        if let Some(tag_ids) = &self.tag_ids
+            && !tag_ids.iter().all(|id| todo.tag_ids.contains(id))
+        {
+            return false;
+        }
+        if self.list_id.as_ref().is_some_and(|id| id != &todo.list_id) {
+            return false;
+        }
+        if self
+            .top_level
+            .is_some_and(|t| t != todo.parent_id.is_none())
+        {
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(GraphQLEnum, Clone, Copy)]
+pub enum TodoSortField {
+    Title,
+    Completed,
+    CreatedAt,
+    UpdatedAt,
+    /// Todos that are not completed sort before completed ones.
+    CompletedAt,
+    /// Todos without a due date sort last.
+    DueAt,
+    Priority,
+    /// The manual order set with `reorderTodo`.
+    Position,
+}
+
+#[derive(GraphQLEnum, Clone, Copy, Default)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(GraphQLInputObject)]
+pub struct TodoOrderBy {
+    pub field: TodoSortField,
+    #[graphql(default)]
+    pub direction: SortDirection,
+}
+
+impl TodoOrderBy {
+    fn compare(&self, a: &Todo, b: &Todo) -> Ordering {
+        let ord = match self.field {
+            TodoSortField::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            TodoSortField::Completed => a.completed.cmp(&b.completed),
+            TodoSortField::CreatedAt => a.created_at.cmp(&b.created_at),
+            TodoSortField::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+            TodoSortField::CompletedAt => a.completed_at.cmp(&b.completed_at),
+            TodoSortField::DueAt => match (a.due_at, b.due_at) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            TodoSortField::Priority => a.priority.cmp(&b.priority),
+            TodoSortField::Position => a.position.cmp(&b.position),
+        };
+        match self.direction {
+            SortDirection::Asc => ord,
+            SortDirection::Desc => ord.reverse(),
+        }
+    }
+}
+
+/// Used when no `orderBy` is given: open todos first, most urgent first, then
+/// soonest due, then in manual order.
+const DEFAULT_ORDER: [TodoOrderBy; 4] = [
+    TodoOrderBy {
+        field: TodoSortField::Completed,
+        direction: SortDirection::Asc,
+    },
+    TodoOrderBy {
+        field: TodoSortField::Priority,
+        direction: SortDirection::Desc,
+    },
+    TodoOrderBy {
+        field: TodoSortField::DueAt,
+        direction: SortDirection::Asc,
+    },
+    TodoOrderBy {
+        field: TodoSortField::Position,
+        direction: SortDirection::Asc,
+    },
+];
+
+/// `order_by`, or [`DEFAULT_ORDER`] if it is empty.
+pub fn effective(order_by: &[TodoOrderBy]) -> &[TodoOrderBy] {
+    if order_by.is_empty() {
+        &DEFAULT_ORDER
+    } else {
+        order_by
+    }
+}
+
+/// Compares by each key of `order_by` in turn (or [`DEFAULT_ORDER`] if
+/// empty). Remaining ties are broken by creation time and then id, so the
+/// order is total and a todo's place can be found again from its fields
+/// alone.
+pub fn compare(order_by: &[TodoOrderBy], a: &Todo, b: &Todo) -> Ordering {
+    effective(order_by)
+        .iter()
+        .map(|o| o.compare(a, b))
+        .find(|ord| ord.is_ne())
+        .unwrap_or_else(|| {
+            a.created_at
+                .cmp(&b.created_at)
+                .then_with(|| a.id.cmp(&b.id))
+        })
+}
+
+/// Drops todos not matching `filter` and sorts the rest by [`compare`].
+pub fn apply(todos: &mut Vec<Todo>, filter: Option<&TodoFilter>, order_by: &[TodoOrderBy]) {
+    if let Some(filter) = filter {
+        todos.retain(|t| filter.matches(t));
+    }
+    todos.sort_by(|a, b| compare(order_by, a, b));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn any() -> TodoFilter {
+        TodoFilter {
+            completed: None,
+            title_contains: None,
+            search: None,
+            created_before: None,
+            created_after: None,
+            priority_in: None,
+            min_priority: None,
+            tag_ids: None,
+            list_id: None,
+            top_level: None,
+        }
+    }
+
+    #[test]
+    fn an_empty_filter_matches_everything() {
+        let mut todo = Todo::new("anything".into());
+        assert!(any().matches(&todo));
+        todo.completed = true;
+        todo.parent_id = Some("parent".into());
+        assert!(any().matches(&todo));
+    }
+
+    #[test]
+    fn matches_text_case_insensitively() {
+        let mut todo = Todo::new("Buy Milk".into());
+        todo.notes = Some("From the Corner shop".into());
+        let title = |s: &str| TodoFilter {
+            title_contains: Some(s.into()),
+            ..any()
+        };
+        let search = |s: &str| TodoFilter {
+            search: Some(s.into()),
+            ..any()
+        };
+        assert!(title("milk").matches(&todo));
+        assert!(!title("corner").matches(&todo));
+        assert!(search("MILK").matches(&todo));
+        assert!(search("corner").matches(&todo));
+        assert!(!search("bakery").matches(&todo));
+    }
+
+    #[test]
+    fn created_bounds_are_exclusive() {
+        let todo = Todo::new("report".into());
+        let at = todo.created_at;
+        let before = |t| TodoFilter {
+            created_before: Some(t),
+            ..any()
+        };
+        let after = |t| TodoFilter {
+            created_after: Some(t),
+            ..any()
+        };
+        assert!(!before(at).matches(&todo));
+        assert!(before(at + Duration::seconds(1)).matches(&todo));
+        assert!(!after(at).matches(&todo));
+        assert!(after(at - Duration::seconds(1)).matches(&todo));
+    }
+
+    #[test]
+    fn combines_every_condition() {
+        let mut todo = Todo::new("report".into());
+        todo.priority = Priority::High;
+        todo.tag_ids = vec!["work".into(), "q3".into()];
+        let filter = TodoFilter {
+            completed: Some(false),
+            min_priority: Some(Priority::Medium),
+            priority_in: Some(vec![Priority::High, Priority::Urgent]),
+            tag_ids: Some(vec!["work".into()]),
+            list_id: Some(todo.list_id.clone()),
+            top_level: Some(true),
+            ..any()
+        };
+        assert!(filter.matches(&todo));
+
+        let mut other = todo.clone();
+        other.completed = true;
+        assert!(!filter.matches(&other));
+        let mut other = todo.clone();
+        other.priority = Priority::Medium;
+        assert!(!filter.matches(&other));
+        let mut other = todo.clone();
+        other.tag_ids.retain(|t| t != "work");
+        assert!(!filter.matches(&other));
+        let mut other = todo.clone();
+        other.list_id = "errands".into();
+        assert!(!filter.matches(&other));
+        let mut other = todo;
+        other.parent_id = Some("parent".into());
+        assert!(!filter.matches(&other));
+    }
+
+    fn titles(todos: &[Todo]) -> Vec<&str> {
+        todos.iter().map(|t| t.title.as_str()).collect()
+    }
+
+    #[test]
+    fn default_order_puts_open_urgent_and_soon_due_todos_first() {
+        let now = Utc::now();
+        let todo = |title: &str, completed, priority, due_in: Option<i64>, position: &str| {
+            let mut todo = Todo::new(title.into());
+            todo.completed = completed;
+            todo.priority = priority;
+            todo.due_at = due_in.map(|days| now + Duration::days(days));
+            todo.position = position.into();
+            todo
+        };
+        let mut todos = vec![
+            todo("done urgent", true, Priority::Urgent, Some(1), "a0"),
+            todo("low", false, Priority::Low, Some(1), "a0"),
+            todo("high undated", false, Priority::High, None, "a0"),
+            todo("high later", false, Priority::High, Some(5), "a0"),
+            todo("high sooner", false, Priority::High, Some(2), "a0"),
+            todo("none second", false, Priority::None, None, "a2"),
+            todo("none first", false, Priority::None, None, "a1"),
+        ];
+        apply(&mut todos, None, &[]);
+        assert_eq!(
+            titles(&todos),
+            [
+                "high sooner",
+                "high later",
+                "high undated",
+                "low",
+                "none first",
+                "none second",
+                "done urgent",
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks_remaining_ties_by_creation_time() {
+        let first = Todo::new("b".into());
+        let mut second = Todo::new("a".into());
+        second.created_at = first.created_at + Duration::seconds(1);
+        let mut todos = vec![second, first];
+        apply(&mut todos, None, &[]);
+        assert_eq!(titles(&todos), ["b", "a"]);
+
+        let order_by = [TodoOrderBy {
+            field: TodoSortField::Title,
+            direction: SortDirection::Asc,
+        }];
+        apply(&mut todos, None, &order_by);
+        assert_eq!(titles(&todos), ["a", "b"]);
+    }
+}
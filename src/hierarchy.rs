@@ -0,0 +1,133 @@
+//! Parent/child links between todos. Only `parent_id` is stored; children and
+//! descendants are derived from a full todo list on demand.
+
+use juniper::GraphQLEnum;
+use std::collections::HashSet;
+
+use crate::todo::Todo;
+
+/// What `deleteTodo` does with the subtasks of the deleted todo.
+#[derive(GraphQLEnum, Clone, Copy, Default)]
+pub enum SubtaskPolicy {
+    /// Subtasks move up to the deleted todo's parent (or the top level).
+    #[default]
+    Reparent,
+    /// Subtasks are deleted too, at every depth.
+    Cascade,
+}
+
+/// Direct subtasks of `id`, in store order.
+pub fn children<'a>(todos: &'a [Todo], id: &'a str) -> impl Iterator<Item = &'a Todo> {
+    todos
+        .iter()
+        .filter(move |t| t.parent_id.as_deref() == Some(id))
+}
+
+/// Subtasks of `id` at every depth, breadth first.
+pub fn descendants<'a>(todos: &'a [Todo], id: &'a str) -> Vec<&'a Todo> {
+    let mut found: Vec<&Todo> = Vec::new();
+    let mut seen = HashSet::from([id]);
+    let mut next = 0;
+    let mut parent = id;
+    loop {
+        for child in children(todos, parent) {
+            // Stored data can't hold cycles, but don't loop forever if it did.
+            if seen.insert(&child.id) {
+                found.push(child);
+            }
+        }
+        let Some(todo) = found.get(next) else {
+            break;
+        };
+        parent = &todo.id;
+        next += 1;
+    }
+    found
+}
+
+/// Whether `ancestor` is `id` itself or one of its parents, at any depth.
+pub fn is_ancestor(todos: &[Todo], ancestor: &str, id: &str) -> bool {
+    let mut current = Some(id);
+    // Each step moves one level up, so the chain is at most `todos.len()` long.
+    for _ in 0..=todos.len() {
+        match current {
+            Some(id) if id == ancestor => return true,
+            Some(id) => {
+                current = todos
+                    .iter()
+                    .find(|t| t.id == id)
+                    .and_then(|t| t.parent_id.as_deref());
+            }
+            None => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(id, parent)` pairs.
+    fn todos(links: &[(&str, Option<&str>)]) -> Vec<Todo> {
+        links
+            .iter()
+            .map(|&(id, parent)| {
+                let mut todo = Todo::new(id.into());
+                todo.id = id.into();
+                todo.parent_id = parent.map(Into::into);
+                todo
+            })
+            .collect()
+    }
+
+    fn ids(todos: Vec<&Todo>) -> Vec<&str> {
+        todos.into_iter().map(|t| t.id.as_str()).collect()
+    }
+
+    #[test]
+    fn descendants_are_found_breadth_first() {
+        let todos = todos(&[
+            ("root", None),
+            ("grandchild", Some("child")),
+            ("child", Some("root")),
+            ("sibling", Some("root")),
+            ("other", None),
+        ]);
+        assert_eq!(
+            ids(children(&todos, "root").collect()),
+            ["child", "sibling"]
+        );
+        assert_eq!(
+            ids(descendants(&todos, "root")),
+            ["child", "sibling", "grandchild"]
+        );
+        assert!(descendants(&todos, "other").is_empty());
+    }
+
+    #[test]
+    fn descendants_stop_at_a_stored_cycle() {
+        let todos = todos(&[("a", Some("b")), ("b", Some("a"))]);
+        assert_eq!(ids(descendants(&todos, "a")), ["b"]);
+    }
+
+    #[test]
+    fn is_ancestor_walks_up_every_level() {
+        let todos = todos(&[
+            ("root", None),
+            ("child", Some("root")),
+            ("grandchild", Some("child")),
+        ]);
+        assert!(is_ancestor(&todos, "root", "root"));
+        assert!(is_ancestor(&todos, "root", "grandchild"));
+        assert!(is_ancestor(&todos, "child", "grandchild"));
+        assert!(!is_ancestor(&todos, "grandchild", "root"));
+        assert!(!is_ancestor(&todos, "root", "missing"));
+    }
+
+    #[test]
+    fn is_ancestor_ends_on_a_stored_cycle() {
+        let todos = todos(&[("a", Some("b")), ("b", Some("a"))]);
+        assert!(!is_ancestor(&todos, "c", "a"));
+    }
+}
@@ -0,0 +1,104 @@
+use juniper::graphql_object;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Context;
+use crate::error::{AppError, AppResult};
+use crate::filter::{self, TodoFilter, TodoOrderBy};
+use crate::store::{StoreResult, TodoStore};
+use crate::todo::Todo;
+
+/// Id of the list new todos go to when none is given. It always exists and
+/// can't be archived or deleted.
+pub const INBOX_ID: &str = "inbox";
+
+pub fn inbox_id() -> String {
+    INBOX_ID.to_owned()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TodoList {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl TodoList {
+    pub fn new(name: String, description: Option<String>) -> Self {
+        TodoList {
+            id: Uuid::new_v4().to_string(),
+            name,
+            description,
+            archived: false,
+        }
+    }
+}
+
+/// Creates the inbox list if the store doesn't have it yet.
+pub fn ensure_inbox(store: &dyn TodoStore) -> StoreResult<()> {
+    if store.lists().get(INBOX_ID)?.is_none() {
+        store.lists().insert(TodoList {
+            id: inbox_id(),
+            name: "Inbox".to_owned(),
+            description: None,
+            archived: false,
+        })?;
+    }
+    Ok(())
+}
+
+/// Looks up a list that todos can be added to: it must exist and must not be
+/// archived. `field` names the argument for the validation error.
+pub fn target(context: &Context, field: &str, id: &str) -> AppResult<TodoList> {
+    let list = context
+        .store
+        .lists()
+        .get(id)?
+        .ok_or_else(|| AppError::not_found("list", id))?;
+    if list.archived {
+        return Err(AppError::invalid(field, "list is archived"));
+    }
+    Ok(list)
+}
+
+#[graphql_object(context = Context)]
+impl TodoList {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Archived lists are hidden from `lists` by default and don't accept
+    /// new todos.
+    fn archived(&self) -> bool {
+        self.archived
+    }
+
+    /// Whether this is the default list new todos are added to.
+    fn is_inbox(&self) -> bool {
+        self.id == INBOX_ID
+    }
+
+    /// Todos in this list, filtered and sorted like `Query.todos`.
+    fn todos(
+        &self,
+        context: &Context,
+        filter: Option<TodoFilter>,
+        order_by: Option<Vec<TodoOrderBy>>,
+    ) -> AppResult<Vec<Todo>> {
+        let mut todos = context.store.todos().list()?;
+        todos.retain(|t| t.list_id == self.id);
+        filter::apply(&mut todos, filter.as_ref(), &order_by.unwrap_or_default());
+        Ok(todos)
+    }
+}
@@ -0,0 +1,245 @@
+//! Live queries: query results that are pushed again whenever the records
+//! behind them may have changed.
+
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::Context;
+use crate::error::AppResult;
+use crate::events::{Cursor, Events};
+use crate::filter::{self, TodoFilter, TodoOrderBy};
+use crate::store::{StoreError, TodoStore};
+use crate::todo::Todo;
+
+struct TodosQuery {
+    store: Arc<dyn TodoStore>,
+    filter: Option<TodoFilter>,
+    order_by: Vec<TodoOrderBy>,
+    events: Events,
+    /// Moved up to the latest change on every run, so a client resuming a
+    /// live query over SSE doesn't replay changes it has already seen.
+    cursor: Option<Cursor>,
+}
+
+/// Everything a selection on a result can show. Two runs with equal
+/// snapshots render the same, whatever the selection.
+#[derive(PartialEq)]
+struct Snapshot(String);
+
+impl TodosQuery {
+    fn run(&self) -> AppResult<(Vec<Todo>, Snapshot)> {
+        if let Some(cursor) = &self.cursor {
+            cursor.set(self.events.head());
+        }
+        let all = self.store.todos().list()?;
+        let mut todos = all.clone();
+        filter::apply(&mut todos, self.filter.as_ref(), &self.order_by);
+        let snapshot = self.snapshot(&todos, all)?;
+        Ok((todos, snapshot))
+    }
+
+    /// The result plus every record reachable from it through fields such as
+    /// `parent`, `children`, `blockedBy`, `blocks`, `list.todos` or
+    /// `tags.todos`, transitively.
+    fn snapshot(&self, todos: &[Todo], mut all: Vec<Todo>) -> AppResult<Snapshot> {
+        let mut reached: HashSet<String> = todos.iter().map(|t| t.id.clone()).collect();
+        let mut tag_ids = HashSet::new();
+        let mut list_ids = HashSet::new();
+        loop {
+            let before = (reached.len(), tag_ids.len(), list_ids.len());
+            for t in &all {
+                let linked = reached.contains(&t.id)
+                    || t.parent_id.as_ref().is_some_and(|p| reached.contains(p))
+                    || t.depends_on.iter().any(|d| reached.contains(d))
+                    || list_ids.contains(&t.list_id)
+                    || t.tag_ids.iter().any(|id| tag_ids.contains(id));
+                if linked {
+                    reached.insert(t.id.clone());
+                    reached.extend(t.parent_id.clone());
+                    reached.extend(t.depends_on.iter().cloned());
+                    list_ids.insert(t.list_id.clone());
+                    tag_ids.extend(t.tag_ids.iter().cloned());
+                }
+            }
+            if before == (reached.len(), tag_ids.len(), list_ids.len()) {
+                break;
+            }
+        }
+        all.retain(|t| reached.contains(&t.id));
+        all.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut tags = self.store.tags().list()?;
+        tags.retain(|t| tag_ids.contains(&t.id));
+        let mut lists = self.store.lists().list()?;
+        lists.retain(|l| list_ids.contains(&l.id));
+        let snapshot =
+            serde_json::to_string(&(todos, all, tags, lists)).map_err(StoreError::from)?;
+        Ok(Snapshot(snapshot))
+    }
+}
+
+/// The result of the `todos` query right away, then again after writes to
+/// todos, tags or lists that change what a selection on it can show. A
+/// selection can reach other records through fields such as `tags`,
+/// `blockedBy` or `children`, so a write that leaves the todos themselves
+/// unchanged can still lead to a push; one that changes nothing reachable
+/// doesn't.
+///
+/// `debounce` is a fixed window, not a trailing debounce: the first write
+/// after a run opens it, and the query runs again once it has passed, however
+/// many writes arrive meanwhile. A steady stream of writes thus leads to a
+/// push every `debounce`, rather than none until the writes stop.
+pub fn todos(
+    context: &Context,
+    filter: Option<TodoFilter>,
+    order_by: Vec<TodoOrderBy>,
+    debounce: Duration,
+) -> BoxStream<'static, AppResult<Vec<Todo>>> {
+    let query = TodosQuery {
+        store: context.store.clone(),
+        filter,
+        order_by,
+        events: context.events.clone(),
+        cursor: context.cursor.clone(),
+    };
+    let writes = context.events.writes();
+    stream::unfold(
+        (query, writes, true, None),
+        move |(query, mut writes, mut first, mut last)| async move {
+            loop {
+                if !first {
+                    writes.changed().await.ok()?;
+                    tokio::time::sleep(debounce).await;
+                    // Everything written during the wait is covered by this run.
+                    writes.mark_unchanged();
+                }
+                first = false;
+                let result = match query.run() {
+                    Ok((_, snapshot)) if last.as_ref() == Some(&snapshot) => continue,
+                    Ok((todos, snapshot)) => {
+                        last = Some(snapshot);
+                        Ok(todos)
+                    }
+                    Err(e) => {
+                        last = None;
+                        Err(e)
+                    }
+                };
+                return Some((result, (query, writes, first, last)));
+            }
+        },
+    )
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::PublishingStore;
+    use crate::list::TodoList;
+    use crate::store::MemoryStore;
+    use crate::tag::Tag;
+    use crate::validation::Limits;
+
+    fn context() -> Context {
+        let events = Events::new(100);
+        Context {
+            store: Arc::new(PublishingStore::new(
+                Arc::new(MemoryStore::default()),
+                events.clone(),
+            )),
+            limits: Limits::default(),
+            events,
+            cursor: None,
+            max_batch_size: 1,
+            graph_lock: Arc::default(),
+            list_lock: Arc::default(),
+        }
+    }
+
+    async fn next(results: &mut BoxStream<'static, AppResult<Vec<Todo>>>) -> Option<Vec<Todo>> {
+        tokio::time::timeout(Duration::from_secs(1), results.next())
+            .await
+            .ok()
+            .flatten()
+            .map(Result::unwrap)
+    }
+
+    #[tokio::test]
+    async fn pushes_again_after_a_tag_is_renamed() {
+        let context = context();
+        let tag = Tag::new("work".into(), None);
+        context.store.tags().insert(tag.clone()).unwrap();
+        let mut todo = Todo::new("report".into());
+        todo.tag_ids.push(tag.id.clone());
+        context.store.todos().insert(todo).unwrap();
+
+        let mut results = todos(&context, None, Vec::new(), Duration::ZERO);
+        assert_eq!(next(&mut results).await.unwrap().len(), 1);
+        context
+            .store
+            .tags()
+            .update(&tag.id, &mut |t| t.name = "office".into())
+            .unwrap();
+        // The todo itself is unchanged, but its `tags` field isn't.
+        assert_eq!(next(&mut results).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn debounces_bursts_of_writes() {
+        let context = context();
+        let mut results = todos(&context, None, Vec::new(), Duration::from_millis(50));
+        assert!(next(&mut results).await.unwrap().is_empty());
+        for title in ["a", "b", "c"] {
+            context
+                .store
+                .todos()
+                .insert(Todo::new(title.into()))
+                .unwrap();
+        }
+        assert_eq!(next(&mut results).await.unwrap().len(), 3);
+        let more = tokio::time::timeout(Duration::from_millis(200), results.next()).await;
+        assert!(more.is_err(), "pushed again without a write");
+    }
+
+    #[tokio::test]
+    async fn skips_writes_that_change_nothing_it_can_show() {
+        let context = context();
+        let work = TodoList::new("Work".into(), None);
+        let home = TodoList::new("Home".into(), None);
+        context.store.lists().insert(work.clone()).unwrap();
+        context.store.lists().insert(home.clone()).unwrap();
+        let mut report = Todo::new("report".into());
+        report.list_id = work.id.clone();
+        context.store.todos().insert(report.clone()).unwrap();
+        let mut dishes = Todo::new("dishes".into());
+        dishes.list_id = home.id.clone();
+        context.store.todos().insert(dishes.clone()).unwrap();
+
+        let filter = TodoFilter {
+            list_id: Some(work.id.clone()),
+            ..TodoFilter::default()
+        };
+        let mut results = todos(&context, Some(filter), Vec::new(), Duration::ZERO);
+        assert_eq!(next(&mut results).await.unwrap().len(), 1);
+
+        let todos = context.store.todos();
+        todos.update(&report.id, &mut |_| {}).unwrap();
+        todos
+            .update(&dishes.id, &mut |t| t.completed = true)
+            .unwrap();
+        context
+            .store
+            .lists()
+            .update(&home.id, &mut |l| l.name = "House".into())
+            .unwrap();
+        let more = tokio::time::timeout(Duration::from_millis(200), results.next()).await;
+        assert!(more.is_err(), "pushed an unchanged result");
+
+        todos
+            .update(&report.id, &mut |t| t.title = "summary".into())
+            .unwrap();
+        assert_eq!(next(&mut results).await.unwrap()[0].title, "summary");
+    }
+}
@@ -1,110 +1,1391 @@
+mod checklist;
+mod dependency;
+mod error;
+mod events;
+mod filter;
+mod hierarchy;
+mod list;
+mod live;
+mod markdown;
+mod pagination;
+mod position;
+mod protocol;
+mod recurrence;
+mod store;
+mod tag;
+mod todo;
+mod validation;
+
 use axum::{
     Router,
     extract::{Extension, Json},
-    response::Html,
-    routing::{get, post},
+    http::{HeaderMap, Method, StatusCode, header},
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use futures::stream::{BoxStream, StreamExt};
+use juniper::http::{GraphQLBatchRequest, GraphQLResponse, graphiql::graphiql_source};
+use juniper::{
+    GraphQLInputObject, Nullable, OperationType, RootNode, graphql_object, graphql_subscription,
 };
-use juniper::http::{GraphQLRequest, graphiql::graphiql_source};
-use juniper::{EmptySubscription, FieldResult, GraphQLObject, RootNode, graphql_object};
+use juniper_axum::{extract::JuniperRequest, subscriptions};
+use juniper_graphql_ws::ConnectionConfig;
+use juniper_subscriptions::Connection;
 use parking_lot::Mutex;
-use serde::{Deserialize, Serialize};
+use shuttle_runtime::{CustomError, SecretStore};
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use uuid::Uuid;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use checklist::ChecklistItem;
+use error::{AppError, AppResult};
+use events::{ChangeKind, Cursor, Events, PublishingStore, TodoChange};
+use filter::{TodoFilter, TodoOrderBy};
+use hierarchy::SubtaskPolicy;
+use list::{INBOX_ID, TodoList};
+use pagination::{PageArgs, TodoConnection};
+use protocol::{Format, GRAPHQL_RESPONSE_JSON};
+use recurrence::Recurrence;
+use store::{JournalStore, MemoryStore, SnapshotStore, SqliteStore, TodoStore};
+use tag::Tag;
+use todo::{Priority, Todo};
+use validation::{Limits, Validator};
 
-#[derive(Clone, Debug, Serialize, Deserialize, GraphQLObject)]
-#[graphql(Context = Context)]
-struct Todo {
-    id: String,
-    title: String,
-    completed: bool,
+/// Fields to change in `updateTodo`; omitted fields are left untouched.
+#[derive(GraphQLInputObject)]
+struct UpdateTodoInput {
+    title: Option<String>,
+    /// Markdown; `null` clears the notes.
+    notes: Nullable<String>,
+    completed: Option<bool>,
+    /// `null` clears the due date.
+    due_at: Nullable<DateTime<Utc>>,
+    priority: Option<Priority>,
+    /// Makes the todo a subtask of another; `null` moves it to the top level.
+    parent_id: Nullable<String>,
+    /// An `RRULE` starting from the (new) due date; `null` stops repeating.
+    recurrence: Nullable<String>,
+    /// IANA zone the recurrence is evaluated in. Left as is when omitted, so
+    /// a new `recurrence` keeps the zone of the old one (UTC for a first one).
+    time_zone: Option<String>,
+}
+
+/// Fields to change in `updateList`; omitted fields are left untouched.
+#[derive(GraphQLInputObject)]
+struct UpdateListInput {
+    name: Option<String>,
+    /// `null` clears the description.
+    description: Nullable<String>,
+    archived: Option<bool>,
 }
 
 #[derive(Clone)]
-struct Context {
-    store: Arc<Mutex<Vec<Todo>>>,
+pub struct Context {
+    store: Arc<dyn TodoStore>,
+    limits: Limits,
+    events: Events,
+    /// Set for subscriptions that resume from an earlier change.
+    cursor: Option<Cursor>,
+    /// Most operations accepted in one batched request.
+    max_batch_size: usize,
+    /// Held across the cycle check and the write by mutations that change
+    /// the subtask or dependency graph, so two of them can't each pass the
+    /// check against a graph the other is about to change, and by
+    /// `deleteTodo`, so nothing gains a parent or dependency that is about
+    /// to be deleted.
+    graph_lock: Arc<Mutex<()>>,
+    /// Held across the check that a list accepts todos and the write that
+    /// puts a todo in it, and by mutations that archive or delete a list, so
+    /// no todo ends up in a list that is gone.
+    list_lock: Arc<Mutex<()>>,
 }
 impl juniper::Context for Context {}
 
 struct QueryRoot;
 struct MutationRoot;
+struct SubscriptionRoot;
 
 #[graphql_object(context = Context)]
 impl QueryRoot {
-    fn todos(context: &Context) -> Vec<Todo> {
-        context.store.lock().clone()
+    /// Todos matching `filter`, sorted by each `orderBy` key in turn. Without
+    /// `orderBy`, open todos come first, by priority and then due date.
+    fn todos(
+        context: &Context,
+        filter: Option<TodoFilter>,
+        order_by: Option<Vec<TodoOrderBy>>,
+    ) -> AppResult<Vec<Todo>> {
+        let mut todos = context.store.todos().list()?;
+        filter::apply(&mut todos, filter.as_ref(), &order_by.unwrap_or_default());
+        Ok(todos)
+    }
+
+    fn todo(context: &Context, id: String) -> AppResult<Option<Todo>> {
+        Ok(context.store.todos().get(&id)?)
+    }
+
+    /// Open todos whose due time has passed, most overdue first.
+    fn overdue_todos(context: &Context) -> AppResult<Vec<Todo>> {
+        let now = Utc::now();
+        let mut todos = context.store.todos().list()?;
+        todos.retain(|t| t.overdue_at(now));
+        todos.sort_by_key(|t| t.due_at);
+        Ok(todos)
+    }
+
+    /// Todos due in `[start, end)`, soonest first.
+    fn due_between(
+        context: &Context,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> AppResult<Vec<Todo>> {
+        if end < start {
+            return Err(AppError::invalid("end", "must not be before start"));
+        }
+        let mut todos = context.store.todos().list()?;
+        todos.retain(|t| t.due_at.is_some_and(|due| start <= due && due < end));
+        todos.sort_by_key(|t| t.due_at);
+        Ok(todos)
     }
 
-    fn todo(context: &Context, id: String) -> Option<Todo> {
-        context.store.lock().iter().find(|t| t.id == id).cloned()
+    /// Relay-style paginated view of `todos`.
+    fn todos_connection(
+        context: &Context,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+        filter: Option<TodoFilter>,
+        order_by: Option<Vec<TodoOrderBy>>,
+    ) -> AppResult<TodoConnection> {
+        let order_by = order_by.unwrap_or_default();
+        let mut todos = context.store.todos().list()?;
+        filter::apply(&mut todos, filter.as_ref(), &order_by);
+        pagination::paginate(
+            todos,
+            &order_by,
+            PageArgs {
+                first,
+                after,
+                last,
+                before,
+            },
+        )
+    }
+
+    /// Todos matching `filter`, each placed after every todo it depends on.
+    /// Otherwise the default `todos` order applies.
+    fn todos_in_dependency_order(
+        context: &Context,
+        filter: Option<TodoFilter>,
+    ) -> AppResult<Vec<Todo>> {
+        let mut todos = context.store.todos().list()?;
+        filter::apply(&mut todos, filter.as_ref(), &[]);
+        Ok(dependency::topological(todos))
+    }
+
+    /// All tags, in creation order.
+    fn tags(context: &Context) -> AppResult<Vec<Tag>> {
+        Ok(context.store.tags().list()?)
+    }
+
+    fn tag(context: &Context, id: String) -> AppResult<Option<Tag>> {
+        Ok(context.store.tags().get(&id)?)
+    }
+
+    /// Lists in creation order, starting with the inbox. Archived lists are
+    /// left out unless `includeArchived` is set.
+    fn lists(
+        context: &Context,
+        #[graphql(default = false)] include_archived: bool,
+    ) -> AppResult<Vec<TodoList>> {
+        let mut lists = context.store.lists().list()?;
+        lists.retain(|l| include_archived || !l.archived);
+        Ok(lists)
+    }
+
+    fn list(context: &Context, id: String) -> AppResult<Option<TodoList>> {
+        Ok(context.store.lists().get(&id)?)
     }
 }
 
 #[graphql_object(context = Context)]
 impl MutationRoot {
-    fn create_todo(context: &Context, title: String) -> FieldResult<Todo> {
-        let todo = Todo {
-            id: Uuid::new_v4().to_string(),
-            title,
-            completed: false,
+    /// Adds a todo to `listId`. Without one, subtasks go to their parent's
+    /// list and other todos to the inbox. A `recurrence` rule needs a `dueAt`,
+    /// which becomes the first occurrence, and is evaluated in the IANA
+    /// `timeZone` (UTC by default).
+    #[allow(clippy::too_many_arguments)]
+    fn create_todo(
+        context: &Context,
+        title: String,
+        notes: Option<String>,
+        due_at: Option<DateTime<Utc>>,
+        priority: Option<Priority>,
+        list_id: Option<String>,
+        parent_id: Option<String>,
+        recurrence: Option<String>,
+        time_zone: Option<String>,
+    ) -> AppResult<Todo> {
+        let mut v = Validator::new(&context.limits);
+        let title = v.title("title", &title);
+        let notes = notes.and_then(|n| v.notes("notes", &n));
+        let rule = recurrence.as_ref().and_then(|r| v.rule("recurrence", r));
+        if rule.is_some() && due_at.is_none() {
+            v.add("recurrence", "a recurring todo needs a due date");
+        }
+        let time_zone = match time_zone {
+            Some(_) if recurrence.is_none() => {
+                v.add("timeZone", "only applies to recurring todos");
+                None
+            }
+            Some(zone) => v.time_zone("timeZone", &zone),
+            None => None,
         };
-        context.store.lock().push(todo.clone());
+        v.finish()?;
+        // Keeps the parent from being deleted before the subtask is stored.
+        let _graph = parent_id.is_some().then(|| context.graph_lock.lock());
+        let parent = match parent_id {
+            Some(id) => Some(
+                context
+                    .store
+                    .todos()
+                    .get(&id)?
+                    .ok_or_else(|| AppError::not_found("todo", id))?,
+            ),
+            None => None,
+        };
+        let list_id = list_id
+            .or_else(|| parent.as_ref().map(|p| p.list_id.clone()))
+            .unwrap_or_else(list::inbox_id);
+        let _lists = context.list_lock.lock();
+        let list = list::target(context, "listId", &list_id)?;
+
+        let mut todo = Todo::new(title);
+        todo.notes = notes;
+        todo.due_at = due_at;
+        todo.priority = priority.unwrap_or_default();
+        todo.list_id = list.id;
+        todo.parent_id = parent.map(|p| p.id);
+        todo.recurrence = rule.zip(due_at).map(|(rule, start)| Recurrence {
+            rule,
+            start,
+            time_zone: time_zone.unwrap_or(Tz::UTC),
+        });
+        todo.position = position::last(context.store.as_ref())?;
+        context.store.todos().insert(todo.clone())?;
+        Ok(todo)
+    }
+
+    /// Completing a recurring todo also creates its next occurrence.
+    fn toggle_todo(context: &Context, id: String) -> AppResult<Todo> {
+        // The next occurrence joins this todo's parent and list.
+        let _graph = context.graph_lock.lock();
+        let _lists = context.list_lock.lock();
+        let mut next = None;
+        let todo = context
+            .store
+            .todos()
+            .update(&id, &mut |t| {
+                let now = Utc::now();
+                next = t.set_completed(!t.completed, now);
+                t.updated_at = now;
+            })?
+            .ok_or_else(|| AppError::not_found("todo", id))?;
+        if let Some(mut next) = next {
+            next.position = position::last(context.store.as_ref())?;
+            context.store.todos().insert(next)?;
+        }
         Ok(todo)
     }
 
-    fn toggle_todo(context: &Context, id: String) -> FieldResult<Option<Todo>> {
-        let mut store = context.store.lock();
-        if let Some(t) = store.iter_mut().find(|t| t.id == id) {
-            t.completed = !t.completed;
-            return Ok(Some(t.clone()));
+    /// Applies every field given in `input` at once, after validating them
+    /// all; a recurring todo must keep a due date. Fails if the new
+    /// `parentId` is the todo itself or one of its subtasks. Completing a
+    /// recurring todo this way also creates its next occurrence, which takes
+    /// over the other changes made in the same call.
+    fn update_todo(context: &Context, id: String, input: UpdateTodoInput) -> AppResult<Todo> {
+        let Some(current) = context.store.todos().get(&id)? else {
+            return Err(AppError::not_found("todo", id));
+        };
+        let mut v = Validator::new(&context.limits);
+        let title = input.title.map(|t| v.title("input.title", &t));
+        let notes = input
+            .notes
+            .explicit()
+            .map(|n| n.and_then(|n| v.notes("input.notes", &n)));
+        let due_at = input.due_at.explicit();
+        let new_due_at = due_at.unwrap_or(current.due_at);
+        let time_zone = input
+            .time_zone
+            .and_then(|z| v.time_zone("input.timeZone", &z));
+        let recurrence = match input.recurrence.explicit() {
+            Some(Some(rule)) => match (v.rule("input.recurrence", &rule), new_due_at) {
+                (Some(rule), Some(start)) => Some(Some(Recurrence {
+                    rule,
+                    start,
+                    time_zone: time_zone
+                        .or(current.recurrence.as_ref().map(|r| r.time_zone))
+                        .unwrap_or(Tz::UTC),
+                })),
+                (Some(_), None) => {
+                    v.add("input.recurrence", "a recurring todo needs a due date");
+                    None
+                }
+                (None, _) => None,
+            },
+            Some(None) => Some(None),
+            None => None,
+        };
+        let keeps_recurrence = recurrence.is_none() && current.recurrence.is_some();
+        if keeps_recurrence && new_due_at.is_none() {
+            v.add("input.dueAt", "a recurring todo needs a due date");
+        }
+        let recurs = matches!(recurrence, Some(Some(_))) || keeps_recurrence;
+        if time_zone.is_some() && !recurs {
+            v.add("input.timeZone", "only applies to recurring todos");
+        }
+        v.finish()?;
+        let parent_id = input.parent_id.explicit();
+        // Held for a next occurrence too, as it joins this todo's parent.
+        let _graph = context.graph_lock.lock();
+        if let Some(Some(parent_id)) = &parent_id {
+            let todos = context.store.todos().list()?;
+            if !todos.iter().any(|t| &t.id == parent_id) {
+                return Err(AppError::not_found("todo", parent_id));
+            }
+            if hierarchy::is_ancestor(&todos, &id, parent_id) {
+                return Err(AppError::invalid(
+                    "input.parentId",
+                    "a todo can't become a subtask of itself or of its own subtasks",
+                ));
+            }
+        }
+
+        // The next occurrence joins this todo's list.
+        let _lists = context.list_lock.lock();
+        let mut next = None;
+        let todo = context
+            .store
+            .todos()
+            .update(&id, &mut |t| {
+                if let Some(title) = &title {
+                    t.title = title.clone();
+                }
+                if let Some(notes) = &notes {
+                    t.notes = notes.clone();
+                }
+                if let Some(due_at) = due_at {
+                    t.due_at = due_at;
+                }
+                if let Some(priority) = input.priority {
+                    t.priority = priority;
+                }
+                if let Some(parent_id) = &parent_id {
+                    t.parent_id = parent_id.clone();
+                }
+                if let Some(recurrence) = &recurrence {
+                    t.recurrence = recurrence.clone();
+                }
+                if let (Some(zone), Some(recurrence)) = (time_zone, &mut t.recurrence) {
+                    recurrence.time_zone = zone;
+                }
+                // Last, so the next occurrence picks up the changes above.
+                let now = Utc::now();
+                if let Some(completed) = input.completed {
+                    next = t.set_completed(completed, now);
+                }
+                t.updated_at = now;
+            })?
+            .ok_or_else(|| AppError::not_found("todo", id))?;
+        if let Some(mut next) = next {
+            next.position = position::last(context.store.as_ref())?;
+            context.store.todos().insert(next)?;
+        }
+        Ok(todo)
+    }
+
+    /// Moves a todo between `beforeId` (the todo that should come right before
+    /// it) and `afterId` (right after it). With only one of them, the todo
+    /// goes directly next to it. Only the moved todo's position changes.
+    fn reorder_todo(
+        context: &Context,
+        id: String,
+        before_id: Option<String>,
+        after_id: Option<String>,
+    ) -> AppResult<Todo> {
+        let store = context.store.todos();
+        let todos = store.list()?;
+        let others: Vec<(&str, &str)> = todos
+            .iter()
+            .filter(|t| t.id != id)
+            .map(|t| (t.id.as_str(), t.position.as_str()))
+            .collect();
+        let position = position::reorder(
+            "todo",
+            &id,
+            &others,
+            before_id.as_deref(),
+            after_id.as_deref(),
+        )?;
+        store
+            .update(&id, &mut |t| {
+                t.position = position.clone();
+                t.updated_at = Utc::now();
+            })?
+            .ok_or_else(|| AppError::not_found("todo", id))
+    }
+
+    /// Appends an unchecked item to the todo's checklist.
+    fn add_checklist_item(context: &Context, todo_id: String, text: String) -> AppResult<Todo> {
+        let mut v = Validator::new(&context.limits);
+        let text = v.title("text", &text);
+        v.finish()?;
+
+        context
+            .store
+            .todos()
+            .update(&todo_id, &mut |t| {
+                let last = t.checklist.iter().map(|i| i.position.as_str()).max();
+                let position = position::between(last, None).unwrap_or_default();
+                t.checklist.push(ChecklistItem::new(text.clone(), position));
+                t.updated_at = Utc::now();
+            })?
+            .ok_or_else(|| AppError::not_found("todo", todo_id))
+    }
+
+    fn toggle_checklist_item(
+        context: &Context,
+        todo_id: String,
+        item_id: String,
+    ) -> AppResult<Todo> {
+        checklist::update_item(context.store.todos(), &todo_id, &item_id, |items, i| {
+            items[i].checked = !items[i].checked;
+            Ok(())
+        })
+    }
+
+    fn remove_checklist_item(
+        context: &Context,
+        todo_id: String,
+        item_id: String,
+    ) -> AppResult<Todo> {
+        checklist::update_item(context.store.todos(), &todo_id, &item_id, |items, i| {
+            items.remove(i);
+            Ok(())
+        })
+    }
+
+    /// Moves a checklist item between `beforeId` and `afterId`, like
+    /// `reorderTodo` does for todos.
+    fn reorder_checklist_item(
+        context: &Context,
+        todo_id: String,
+        item_id: String,
+        before_id: Option<String>,
+        after_id: Option<String>,
+    ) -> AppResult<Todo> {
+        checklist::update_item(context.store.todos(), &todo_id, &item_id, |items, i| {
+            let others: Vec<(&str, &str)> = items
+                .iter()
+                .filter(|item| item.id != item_id)
+                .map(|item| (item.id.as_str(), item.position.as_str()))
+                .collect();
+            let position = position::reorder(
+                "checklist item",
+                &item_id,
+                &others,
+                before_id.as_deref(),
+                after_id.as_deref(),
+            )?;
+            items[i].position = position;
+            Ok(())
+        })
+    }
+
+    /// Returns `true` on success; a missing id is a `NOT_FOUND` error.
+    /// `subtasks` decides whether subtasks are deleted along with the todo or
+    /// moved up a level.
+    fn delete_todo(
+        context: &Context,
+        id: String,
+        #[graphql(default)] subtasks: SubtaskPolicy,
+    ) -> AppResult<bool> {
+        // Nothing may gain a parent or dependency in the subtree meanwhile.
+        let _graph = context.graph_lock.lock();
+        let store = context.store.todos();
+        let Some(todo) = store.get(&id)? else {
+            return Err(AppError::not_found("todo", id));
+        };
+        let todos = store.list()?;
+        let mut deleted = vec![id.clone()];
+        match subtasks {
+            SubtaskPolicy::Cascade => {
+                for child in hierarchy::descendants(&todos, &id) {
+                    store.delete(&child.id)?;
+                    deleted.push(child.id.clone());
+                }
+            }
+            SubtaskPolicy::Reparent => {
+                for child in hierarchy::children(&todos, &id) {
+                    store.update(&child.id, &mut |t| {
+                        t.parent_id = todo.parent_id.clone();
+                        t.updated_at = Utc::now();
+                    })?;
+                }
+            }
+        }
+        store.delete(&id)?;
+        // Nothing can be blocked by a todo that no longer exists.
+        for t in &todos {
+            if t.depends_on.iter().any(|d| deleted.contains(d)) && !deleted.contains(&t.id) {
+                store.update(&t.id, &mut |t| {
+                    t.depends_on.retain(|d| !deleted.contains(d))
+                })?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Records that `todoId` can't start until `dependsOnId` is completed.
+    /// Fails if `dependsOnId` already depends on `todoId`, directly or through
+    /// other todos. Adding an existing dependency is a no-op.
+    fn add_dependency(
+        context: &Context,
+        todo_id: String,
+        depends_on_id: String,
+    ) -> AppResult<Todo> {
+        let _graph = context.graph_lock.lock();
+        let store = context.store.todos();
+        let todos = store.list()?;
+        for id in [&todo_id, &depends_on_id] {
+            if !todos.iter().any(|t| &t.id == id) {
+                return Err(AppError::not_found("todo", id));
+            }
+        }
+        if todo_id == depends_on_id {
+            return Err(AppError::invalid(
+                "dependsOnId",
+                "a todo can't depend on itself",
+            ));
+        }
+        if let Some(chain) = dependency::path(&todos, &depends_on_id, &todo_id) {
+            return Err(AppError::invalid(
+                "dependsOnId",
+                dependency::cycle_message(&chain),
+            ));
+        }
+        store
+            .update(&todo_id, &mut |t| {
+                if !t.depends_on.contains(&depends_on_id) {
+                    t.depends_on.push(depends_on_id.clone());
+                    t.updated_at = Utc::now();
+                }
+            })?
+            .ok_or_else(|| AppError::not_found("todo", todo_id))
+    }
+
+    fn remove_dependency(
+        context: &Context,
+        todo_id: String,
+        depends_on_id: String,
+    ) -> AppResult<Todo> {
+        context
+            .store
+            .todos()
+            .update(&todo_id, &mut |t| {
+                if t.depends_on.contains(&depends_on_id) {
+                    t.depends_on.retain(|d| d != &depends_on_id);
+                    t.updated_at = Utc::now();
+                }
+            })?
+            .ok_or_else(|| AppError::not_found("todo", todo_id))
+    }
+
+    fn move_todo(context: &Context, id: String, list_id: String) -> AppResult<Todo> {
+        let _lists = context.list_lock.lock();
+        let list = list::target(context, "listId", &list_id)?;
+        context
+            .store
+            .todos()
+            .update(&id, &mut |t| {
+                if t.list_id != list.id {
+                    t.list_id = list.id.clone();
+                    t.updated_at = Utc::now();
+                }
+            })?
+            .ok_or_else(|| AppError::not_found("todo", id))
+    }
+
+    fn create_list(
+        context: &Context,
+        name: String,
+        description: Option<String>,
+    ) -> AppResult<TodoList> {
+        let mut v = Validator::new(&context.limits);
+        let name = v.title("name", &name);
+        let description = description.and_then(|d| v.description("description", &d));
+        v.finish()?;
+
+        let list = TodoList::new(name, description);
+        context.store.lists().insert(list.clone())?;
+        Ok(list)
+    }
+
+    /// The inbox can be renamed but not archived.
+    fn update_list(context: &Context, id: String, input: UpdateListInput) -> AppResult<TodoList> {
+        let mut v = Validator::new(&context.limits);
+        let name = input.name.map(|n| v.title("input.name", &n));
+        let description = input
+            .description
+            .explicit()
+            .map(|d| d.and_then(|d| v.description("input.description", &d)));
+        if id == INBOX_ID && input.archived == Some(true) {
+            v.add("input.archived", "the inbox can't be archived");
+        }
+        v.finish()?;
+
+        let _lists = context.list_lock.lock();
+        context
+            .store
+            .lists()
+            .update(&id, &mut |l| {
+                if let Some(name) = &name {
+                    l.name = name.clone();
+                }
+                if let Some(description) = &description {
+                    l.description = description.clone();
+                }
+                if let Some(archived) = input.archived {
+                    l.archived = archived;
+                }
+            })?
+            .ok_or_else(|| AppError::not_found("list", id))
+    }
+
+    /// Deletes a list after moving its todos to the inbox. The inbox itself
+    /// can't be deleted.
+    fn delete_list(context: &Context, id: String) -> AppResult<bool> {
+        if id == INBOX_ID {
+            return Err(AppError::invalid("id", "the inbox can't be deleted"));
+        }
+        let _lists = context.list_lock.lock();
+        if context.store.lists().get(&id)?.is_none() {
+            return Err(AppError::not_found("list", id));
+        }
+        let todos = context.store.todos();
+        for todo in todos.list()? {
+            if todo.list_id == id {
+                todos.update(&todo.id, &mut |t| {
+                    t.list_id = INBOX_ID.to_owned();
+                    t.updated_at = Utc::now();
+                })?;
+            }
+        }
+        context.store.lists().delete(&id)?;
+        Ok(true)
+    }
+
+    fn create_tag(context: &Context, name: String, color: Option<String>) -> AppResult<Tag> {
+        let mut v = Validator::new(&context.limits);
+        let name = v.tag_name("name", &name);
+        let color = color.map(|c| v.color("color", &c));
+        v.finish()?;
+
+        let tag = Tag::new(name, color);
+        context
+            .store
+            .tags()
+            .insert(tag.clone())
+            .map_err(tag::name_conflict)?;
+        Ok(tag)
+    }
+
+    fn rename_tag(context: &Context, id: String, name: String) -> AppResult<Tag> {
+        let mut v = Validator::new(&context.limits);
+        let name = v.tag_name("name", &name);
+        v.finish()?;
+
+        context
+            .store
+            .tags()
+            .update(&id, &mut |t| t.name = name.clone())
+            .map_err(tag::name_conflict)?
+            .ok_or_else(|| AppError::not_found("tag", id))
+    }
+
+    /// Detaches the tag from every todo carrying it, then deletes it.
+    fn delete_tag(context: &Context, id: String) -> AppResult<bool> {
+        if context.store.tags().get(&id)?.is_none() {
+            return Err(AppError::not_found("tag", id));
+        }
+        let todos = context.store.todos();
+        for todo in todos.list()? {
+            if todo.tag_ids.contains(&id) {
+                todos.update(&todo.id, &mut |t| t.tag_ids.retain(|t| t != &id))?;
+            }
+        }
+        context.store.tags().delete(&id)?;
+        Ok(true)
+    }
+
+    /// Adding a tag the todo already carries is a no-op.
+    fn add_tag_to_todo(context: &Context, todo_id: String, tag_id: String) -> AppResult<Todo> {
+        if context.store.tags().get(&tag_id)?.is_none() {
+            return Err(AppError::not_found("tag", tag_id));
+        }
+        context
+            .store
+            .todos()
+            .update(&todo_id, &mut |t| {
+                if !t.tag_ids.contains(&tag_id) {
+                    t.tag_ids.push(tag_id.clone());
+                    t.updated_at = Utc::now();
+                }
+            })?
+            .ok_or_else(|| AppError::not_found("todo", todo_id))
+    }
+
+    fn remove_tag_from_todo(context: &Context, todo_id: String, tag_id: String) -> AppResult<Todo> {
+        context
+            .store
+            .todos()
+            .update(&todo_id, &mut |t| {
+                if t.tag_ids.contains(&tag_id) {
+                    t.tag_ids.retain(|id| id != &tag_id);
+                    t.updated_at = Utc::now();
+                }
+            })?
+            .ok_or_else(|| AppError::not_found("todo", todo_id))
+    }
+}
+
+/// Changes are pushed after the mutation that made them has been stored.
+#[graphql_subscription(context = Context)]
+impl SubscriptionRoot {
+    /// Every created, updated and deleted todo, in the order they happened.
+    async fn todo_changes(context: &Context) -> BoxStream<'static, TodoChange> {
+        context.events.subscribe(context.cursor.clone())
+    }
+
+    async fn todo_created(context: &Context) -> BoxStream<'static, Todo> {
+        changed_todos(context, ChangeKind::Created)
+    }
+
+    /// Todos as they are after each update, including updates made as a side
+    /// effect of another mutation, such as moving todos out of a deleted list.
+    async fn todo_updated(context: &Context) -> BoxStream<'static, Todo> {
+        changed_todos(context, ChangeKind::Updated)
+    }
+
+    /// Live version of the `todos` query: the result right away, then again
+    /// after writes that change anything a selection on it can show, so
+    /// fields resolved from other records such as `tags`, `isBlocked` or
+    /// `progress` stay current. The first write after a push opens a fixed
+    /// window of `debounceMs`; the query runs again when it closes, however
+    /// many writes arrive meanwhile.
+    async fn live_todos(
+        context: &Context,
+        filter: Option<TodoFilter>,
+        order_by: Option<Vec<TodoOrderBy>>,
+        #[graphql(default = 100)] debounce_ms: i32,
+    ) -> AppResult<BoxStream<'static, AppResult<Vec<Todo>>>> {
+        if !(0..=10_000).contains(&debounce_ms) {
+            return Err(AppError::invalid(
+                "debounceMs",
+                "must be between 0 and 10000",
+            ));
         }
-        Ok(None)
+        Ok(live::todos(
+            context,
+            filter,
+            order_by.unwrap_or_default(),
+            Duration::from_millis(debounce_ms as u64),
+        ))
     }
 
-    fn delete_todo(context: &Context, id: String) -> FieldResult<bool> {
-        let mut store = context.store.lock();
-        let orig_len = store.len();
-        store.retain(|t| t.id != id);
-        Ok(store.len() != orig_len)
+    /// Ids of deleted todos.
+    async fn todo_deleted(context: &Context) -> BoxStream<'static, String> {
+        context
+            .events
+            .subscribe(context.cursor.clone())
+            .filter_map(|c| async move { (c.kind == ChangeKind::Deleted).then_some(c.id) })
+            .boxed()
     }
 }
 
-type Schema = RootNode<'static, QueryRoot, MutationRoot, EmptySubscription<Context>>;
+fn changed_todos(context: &Context, kind: ChangeKind) -> BoxStream<'static, Todo> {
+    context
+        .events
+        .subscribe(context.cursor.clone())
+        .filter_map(move |c| async move { c.todo.filter(|_| c.kind == kind) })
+        .boxed()
+}
+
+type Schema = RootNode<'static, QueryRoot, MutationRoot, SubscriptionRoot>;
 
 async fn graphiql() -> Html<String> {
-    Html(graphiql_source("/graphql", None))
+    Html(graphiql_source("/graphql", Some("/subscriptions")))
 }
 
+/// Serves queries and mutations per the GraphQL-over-HTTP spec: `POST`
+/// with a JSON body, or `GET` with URL parameters for anything but mutations.
+/// A `POST` body may also be an array of operations, answered with an array
+/// of responses.
 async fn graphql_handler(
+    method: Method,
+    headers: HeaderMap,
     Extension(schema): Extension<Arc<Schema>>,
     Extension(context): Extension<Context>,
-    Json(req): Json<GraphQLRequest>,
-) -> Json<juniper::http::GraphQLResponse> {
-    let res = req.execute(&schema, &context).await;
-    Json(res)
+    JuniperRequest(req): JuniperRequest,
+) -> Response {
+    let Some(format) = Format::negotiate(&headers) else {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            format!("responses are sent as {GRAPHQL_RESPONSE_JSON} or application/json"),
+        )
+            .into_response();
+    };
+    let req = match req {
+        GraphQLBatchRequest::Single(req) => req,
+        GraphQLBatchRequest::Batch(reqs) => {
+            if reqs.is_empty() || reqs.len() > context.max_batch_size {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "batches must hold 1 to {} operations",
+                        context.max_batch_size
+                    ),
+                )
+                    .into_response();
+            }
+            // One at a time, so later operations see what earlier mutations did.
+            let mut responses = Vec::with_capacity(reqs.len());
+            for req in &reqs {
+                responses.push(req.execute(&schema, &context).await);
+            }
+            return format.respond_batch(responses);
+        }
+    };
+    if method == Method::GET
+        && protocol::operation_type(&schema, &req) == Some(OperationType::Mutation)
+    {
+        return (
+            StatusCode::METHOD_NOT_ALLOWED,
+            [(header::ALLOW, "POST")],
+            "mutations must be sent with POST",
+        )
+            .into_response();
+    }
+    format.respond(req.execute(&schema, &context).await)
 }
 
-#[shuttle_runtime::main]
-async fn main() -> shuttle_axum::ShuttleAxum {
-    let initial = vec![Todo {
-        id: Uuid::new_v4().to_string(),
-        title: "Buy milk".into(),
-        completed: false,
-    }];
-
-    let store = Arc::new(Mutex::new(initial));
-    let ctx = Context { store };
-
-    let schema = Arc::new(Schema::new(
-        QueryRoot,
-        MutationRoot,
-        EmptySubscription::new(),
-    ));
-
-    let app = Router::new()
-        .route("/graphql", post(graphql_handler))
+/// Runs a subscription and streams its results as server-sent events: one
+/// `next` event per result, carrying a GraphQL response, then `complete`.
+/// Each event id is the sequence number of the change behind it, so a client
+/// reconnecting with `Last-Event-ID` picks up where it left off as long as
+/// the change buffer still holds the changes it missed.
+async fn graphql_sse_handler(
+    Extension(schema): Extension<Arc<Schema>>,
+    Extension(context): Extension<Context>,
+    headers: HeaderMap,
+    JuniperRequest(req): JuniperRequest,
+) -> Response {
+    let GraphQLBatchRequest::Single(req) = req else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "batched requests can't be streamed",
+        )
+            .into_response();
+    };
+    let last_event_id = headers.get("last-event-id").and_then(|v| v.to_str().ok());
+    let cursor = Cursor::new(context.events.resume_from(last_event_id));
+    let context = Context {
+        cursor: Some(cursor.clone()),
+        ..context
+    };
+
+    // The result stream borrows the request, schema and context, so it runs
+    // on its own task and hands events over through a channel.
+    let (started_tx, started_rx) = oneshot::channel::<Result<(), GraphQLResponse>>();
+    let (tx, mut rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut results = match juniper::http::resolve_into_stream(&req, &schema, &context).await {
+            Ok((value, errors)) => {
+                let _ = started_tx.send(Ok(()));
+                Connection::from_stream(value, errors)
+            }
+            Err(e) => {
+                let _ = started_tx.send(Err(GraphQLResponse::from_result(Err(e))));
+                return;
+            }
+        };
+        loop {
+            tokio::select! {
+                result = results.next() => {
+                    let Some(result) = result else { break };
+                    let Ok(event) = Event::default()
+                        .event("next")
+                        .id(context.events.event_id(cursor.get()))
+                        .json_data(result)
+                    else {
+                        break;
+                    };
+                    if tx.send(event).await.is_err() {
+                        return;
+                    }
+                }
+                _ = tx.closed() => return,
+            }
+        }
+        let _ = tx.send(Event::default().event("complete").data("")).await;
+    });
+
+    match started_rx.await {
+        Ok(Ok(())) => {}
+        Ok(Err(res)) => return (StatusCode::BAD_REQUEST, Json(res)).into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+    let events =
+        futures::stream::poll_fn(move |cx| rx.poll_recv(cx).map(|e| e.map(Ok::<_, Infallible>)));
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn secret_or<T: std::str::FromStr>(
+    secrets: &SecretStore,
+    key: &str,
+    default: T,
+) -> Result<T, CustomError>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match secrets.get(key) {
+        Some(value) => value
+            .parse()
+            .map_err(|e| CustomError::new(e).context(format!("invalid {key}"))),
+        None => Ok(default),
+    }
+}
+
+/// Picks the storage backend from the `STORE_BACKEND` secret (`memory`,
+/// `sqlite`, `file` or `journal`), defaulting to the seeded in-memory store.
+fn open_store(secrets: &SecretStore) -> Result<Arc<dyn TodoStore>, CustomError> {
+    match secrets.get("STORE_BACKEND").as_deref() {
+        Some("sqlite") => {
+            let path = secrets
+                .get("SQLITE_PATH")
+                .unwrap_or_else(|| "todos.db".into());
+            Ok(Arc::new(SqliteStore::open(&path)?))
+        }
+        Some("file") => {
+            let path = secrets
+                .get("SNAPSHOT_PATH")
+                .unwrap_or_else(|| "todos.json".into());
+            let interval = secret_or(secrets, "SNAPSHOT_INTERVAL_SECS", 5)?;
+            let store: Arc<dyn TodoStore> = Arc::new(SnapshotStore::open(path)?);
+            store::flush_every(&store, Duration::from_secs(interval));
+            Ok(store)
+        }
+        Some("journal") => {
+            let journal = secrets
+                .get("JOURNAL_PATH")
+                .unwrap_or_else(|| "todos.log".into());
+            let snapshot = secrets
+                .get("SNAPSHOT_PATH")
+                .unwrap_or_else(|| "todos.json".into());
+            let interval = secret_or(secrets, "COMPACT_INTERVAL_SECS", 300)?;
+            let store: Arc<dyn TodoStore> = Arc::new(JournalStore::open(journal, snapshot)?);
+            store::flush_every(&store, Duration::from_secs(interval));
+            Ok(store)
+        }
+        Some("memory") | None => {
+            let initial = vec![Todo::new("Buy milk".into())];
+            Ok(Arc::new(MemoryStore::new(initial)))
+        }
+        Some(other) => Err(CustomError::msg(format!("unknown STORE_BACKEND {other:?}"))),
+    }
+}
+
+/// Flushes the store when dropped. The Shuttle runtime drops the service
+/// future on SIGTERM/SIGINT right before exiting, so this is the last chance
+/// to persist buffered changes.
+struct FlushOnDrop(Arc<dyn TodoStore>);
+
+impl Drop for FlushOnDrop {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.flush() {
+            eprintln!("failed to flush store on shutdown: {e}");
+        }
+    }
+}
+
+struct TodoService {
+    router: Router,
+    store: Arc<dyn TodoStore>,
+}
+
+#[shuttle_runtime::async_trait]
+impl shuttle_runtime::Service for TodoService {
+    async fn bind(mut self, addr: SocketAddr) -> Result<(), shuttle_runtime::Error> {
+        let _flush = FlushOnDrop(self.store);
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(CustomError::new)?;
+        axum::serve(listener, self.router)
+            .await
+            .map_err(CustomError::new)?;
+        Ok(())
+    }
+}
+
+/// The HTTP routes, sharing one schema and `ctx`.
+fn app(ctx: Context) -> Router {
+    let schema = Arc::new(Schema::new(QueryRoot, MutationRoot, SubscriptionRoot));
+
+    Router::new()
+        .route("/graphql", get(graphql_handler).post(graphql_handler))
+        .route(
+            "/graphql/stream",
+            get(graphql_sse_handler).post(graphql_sse_handler),
+        )
+        .route(
+            "/subscriptions",
+            get(subscriptions::graphql_transport_ws::<Arc<Schema>>(
+                ConnectionConfig::new(ctx.clone()),
+            )),
+        )
         .route("/graphiql", get(graphiql))
         .layer(Extension(schema))
-        .layer(Extension(ctx));
+        .layer(Extension(ctx))
+}
 
-    Ok(app.into())
+#[shuttle_runtime::main]
+async fn main(
+    #[shuttle_runtime::Secrets] secrets: SecretStore,
+) -> Result<TodoService, shuttle_runtime::Error> {
+    let store = open_store(&secrets)?;
+    list::ensure_inbox(store.as_ref()).map_err(CustomError::new)?;
+    position::backfill(store.as_ref()).map_err(CustomError::new)?;
+    let events = Events::new(secret_or(&secrets, "CHANGE_BUFFER_SIZE", 1000)?);
+    let ctx = Context {
+        store: Arc::new(PublishingStore::new(store.clone(), events.clone())),
+        limits: Limits {
+            title_max_len: secret_or(&secrets, "TITLE_MAX_LEN", Limits::default().title_max_len)?,
+            tag_name_max_len: secret_or(
+                &secrets,
+                "TAG_NAME_MAX_LEN",
+                Limits::default().tag_name_max_len,
+            )?,
+            description_max_len: secret_or(
+                &secrets,
+                "DESCRIPTION_MAX_LEN",
+                Limits::default().description_max_len,
+            )?,
+            notes_max_len: secret_or(&secrets, "NOTES_MAX_LEN", Limits::default().notes_max_len)?,
+        },
+        events,
+        cursor: None,
+        max_batch_size: secret_or(&secrets, "MAX_BATCH_SIZE", 10)?,
+        graph_lock: Arc::default(),
+        list_lock: Arc::default(),
+    };
+
+    Ok(TodoService {
+        router: app(ctx),
+        store,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn context() -> Context {
+        let store: Arc<dyn TodoStore> = Arc::new(MemoryStore::default());
+        list::ensure_inbox(store.as_ref()).unwrap();
+        let events = Events::new(100);
+        Context {
+            store: Arc::new(PublishingStore::new(store, events.clone())),
+            limits: Limits::default(),
+            events,
+            cursor: None,
+            max_batch_size: 2,
+            graph_lock: Arc::default(),
+            list_lock: Arc::default(),
+        }
+    }
+
+    async fn execute(context: &Context, query: &str) -> Value {
+        let schema = Schema::new(QueryRoot, MutationRoot, SubscriptionRoot);
+        let response = juniper::http::GraphQLRequest::new(query.into(), None, None)
+            .execute(&schema, context)
+            .await;
+        serde_json::to_value(response).unwrap()
+    }
+
+    /// Runs `query` and returns its `data`, failing on any error.
+    async fn run(context: &Context, query: &str) -> Value {
+        let response = execute(context, query).await;
+        assert!(response.get("errors").is_none(), "{response}");
+        response["data"].clone()
+    }
+
+    /// Runs `query`, which must fail, and returns the code of its error.
+    async fn error_code(context: &Context, query: &str) -> String {
+        let response = execute(context, query).await;
+        response["errors"][0]["extensions"]["code"]
+            .as_str()
+            .unwrap_or_else(|| panic!("no error in {response}"))
+            .to_owned()
+    }
+
+    async fn create_list(context: &Context, name: &str) -> String {
+        let data = run(
+            context,
+            &format!(r#"mutation {{ createList(name: "{name}") {{ id }} }}"#),
+        )
+        .await;
+        data["createList"]["id"].as_str().unwrap().to_owned()
+    }
+
+    #[tokio::test]
+    async fn new_todos_go_to_the_inbox() {
+        let context = context();
+        let data = run(
+            &context,
+            r#"mutation { createTodo(title: "a") { list { id isInbox } } }"#,
+        )
+        .await;
+        assert_eq!(data["createTodo"]["list"]["id"], INBOX_ID);
+        assert_eq!(data["createTodo"]["list"]["isInbox"], true);
+    }
+
+    #[tokio::test]
+    async fn archived_lists_take_no_new_todos() {
+        let context = context();
+        let list = create_list(&context, "Errands").await;
+        let data = run(&context, r#"mutation { createTodo(title: "a") { id } }"#).await;
+        let todo = data["createTodo"]["id"].as_str().unwrap();
+        run(
+            &context,
+            &format!(
+                r#"mutation {{ updateList(id: "{list}", input: {{ archived: true }}) {{ id }} }}"#
+            ),
+        )
+        .await;
+
+        let create = format!(r#"mutation {{ createTodo(title: "b", listId: "{list}") {{ id }} }}"#);
+        assert_eq!(error_code(&context, &create).await, "VALIDATION_FAILED");
+        let mv = format!(r#"mutation {{ moveTodo(id: "{todo}", listId: "{list}") {{ id }} }}"#);
+        assert_eq!(error_code(&context, &mv).await, "VALIDATION_FAILED");
+        let archive_inbox =
+            r#"mutation { updateList(id: "inbox", input: { archived: true }) { id } }"#;
+        assert_eq!(
+            error_code(&context, archive_inbox).await,
+            "VALIDATION_FAILED"
+        );
+    }
+
+    #[tokio::test]
+    async fn deleting_a_list_moves_its_todos_to_the_inbox() {
+        let context = context();
+        let list = create_list(&context, "Errands").await;
+        let data = run(
+            &context,
+            &format!(r#"mutation {{ createTodo(title: "a", listId: "{list}") {{ id }} }}"#),
+        )
+        .await;
+        let todo = data["createTodo"]["id"].as_str().unwrap();
+
+        let data = run(
+            &context,
+            &format!(r#"mutation {{ deleteList(id: "{list}") }}"#),
+        )
+        .await;
+        assert_eq!(data["deleteList"], true);
+        let data = run(
+            &context,
+            &format!(r#"{{ todo(id: "{todo}") {{ list {{ id }} }} lists {{ id }} }}"#),
+        )
+        .await;
+        assert_eq!(data["todo"]["list"]["id"], INBOX_ID);
+        assert_eq!(data["lists"], serde_json::json!([{ "id": INBOX_ID }]));
+
+        let again = format!(r#"mutation {{ deleteList(id: "{list}") }}"#);
+        assert_eq!(error_code(&context, &again).await, "NOT_FOUND");
+        let inbox = r#"mutation { deleteList(id: "inbox") }"#;
+        assert_eq!(error_code(&context, inbox).await, "VALIDATION_FAILED");
+    }
+
+    async fn create_todo(context: &Context, title: &str, parent: Option<&str>) -> String {
+        let parent = parent.map_or(String::new(), |p| format!(r#", parentId: "{p}""#));
+        let query = format!(r#"mutation {{ createTodo(title: "{title}"{parent}) {{ id }} }}"#);
+        let data = run(context, &query).await;
+        data["createTodo"]["id"].as_str().unwrap().to_owned()
+    }
+
+    /// `(title, parent title)` of every todo, sorted.
+    async fn tree(context: &Context) -> Vec<(String, Option<String>)> {
+        let data = run(context, "{ todos { title parent { title } } }").await;
+        let mut tree: Vec<_> = data["todos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| {
+                let parent = t["parent"]["title"].as_str().map(str::to_owned);
+                (t["title"].as_str().unwrap().to_owned(), parent)
+            })
+            .collect();
+        tree.sort();
+        tree
+    }
+
+    #[tokio::test]
+    async fn deleting_a_todo_moves_its_subtasks_up() {
+        let context = context();
+        let root = create_todo(&context, "root", None).await;
+        let middle = create_todo(&context, "middle", Some(&root)).await;
+        let leaf = create_todo(&context, "leaf", Some(&middle)).await;
+        let blocked = create_todo(&context, "blocked", None).await;
+        run(
+            &context,
+            &format!(r#"mutation {{ addDependency(todoId: "{blocked}", dependsOnId: "{middle}") {{ id }} }}"#),
+        )
+        .await;
+
+        let data = run(
+            &context,
+            &format!(r#"mutation {{ deleteTodo(id: "{middle}") }}"#),
+        )
+        .await;
+        assert_eq!(data["deleteTodo"], true);
+        assert_eq!(
+            tree(&context).await,
+            [
+                ("blocked".to_owned(), None),
+                ("leaf".to_owned(), Some("root".to_owned())),
+                ("root".to_owned(), None),
+            ]
+        );
+        let data = run(
+            &context,
+            &format!(r#"{{ todo(id: "{blocked}") {{ blockedBy {{ id }} }} }}"#),
+        )
+        .await;
+        assert_eq!(data["todo"]["blockedBy"], serde_json::json!([]));
+        let data = run(&context, &format!(r#"{{ todo(id: "{leaf}") {{ id }} }}"#)).await;
+        assert_eq!(data["todo"]["id"], leaf.as_str());
+    }
+
+    #[tokio::test]
+    async fn deleting_a_todo_can_cascade_to_its_subtasks() {
+        let context = context();
+        let root = create_todo(&context, "root", None).await;
+        let middle = create_todo(&context, "middle", Some(&root)).await;
+        create_todo(&context, "leaf", Some(&middle)).await;
+        create_todo(&context, "other", None).await;
+
+        let delete = format!(r#"mutation {{ deleteTodo(id: "{root}", subtasks: CASCADE) }}"#);
+        assert_eq!(run(&context, &delete).await["deleteTodo"], true);
+        assert_eq!(tree(&context).await, [("other".to_owned(), None)]);
+        assert_eq!(error_code(&context, &delete).await, "NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn subtasks_need_an_existing_parent() {
+        let context = context();
+        let query = r#"mutation { createTodo(title: "a", parentId: "missing") { id } }"#;
+        assert_eq!(error_code(&context, query).await, "NOT_FOUND");
+    }
+
+    /// Sends `request` through the router and returns its status, headers
+    /// and body.
+    async fn send(
+        context: Context,
+        request: axum::http::Request<axum::body::Body>,
+    ) -> (StatusCode, HeaderMap, String) {
+        use tower::ServiceExt;
+
+        let response = app(context).oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn mutations_over_get_are_not_allowed() {
+        let context = context();
+        let query = "mutation%20%7B%20createTodo(title%3A%20%22a%22)%20%7B%20id%20%7D%20%7D";
+        let request = axum::http::Request::get(format!("/graphql?query={query}"))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let (status, headers, _) = send(context.clone(), request).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(headers[header::ALLOW], "POST");
+        assert_eq!(tree(&context).await, []);
+
+        let request = axum::http::Request::get("/graphql?query=%7B%20todos%20%7B%20id%20%7D%20%7D")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let (status, _, body) = send(context, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"data":{"todos":[]}}"#);
+    }
+
+    /// Posts `body` to `/graphql` as JSON.
+    async fn post(context: Context, body: Value) -> (StatusCode, String) {
+        let request = axum::http::Request::post("/graphql")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        let (status, _, body) = send(context, request).await;
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn batches_must_fit_the_limit() {
+        let context = context();
+        let todos = serde_json::json!({ "query": "{ todos { id } }" });
+        let (status, _) = post(context.clone(), serde_json::json!([])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let batch = serde_json::json!([todos, todos, todos]);
+        let (status, body) = post(context.clone(), batch).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, "batches must hold 1 to 2 operations");
+        let (status, body) = post(context, serde_json::json!([todos, todos])).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap(),
+            serde_json::json!([{ "data": { "todos": [] } }, { "data": { "todos": [] } }])
+        );
+    }
+
+    #[tokio::test]
+    async fn batches_run_in_order() {
+        let context = context();
+        let batch = serde_json::json!([
+            { "query": r#"mutation { createTodo(title: "first") { id } }"# },
+            { "query": "{ todos { title } }" },
+        ]);
+        let (status, body) = post(context, batch).await;
+        assert_eq!(status, StatusCode::OK);
+        let responses: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            responses[1]["data"]["todos"],
+            serde_json::json!([{ "title": "first" }])
+        );
+    }
 }
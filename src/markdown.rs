@@ -0,0 +1,271 @@
+//! Markdown rendering for todo notes, on top of `pulldown-cmark`.
+//!
+//! CommonMark plus strikethrough. The output is safe by construction: text
+//! and code are HTML-escaped, raw HTML in the source is shown as text, images
+//! are reduced to their alt text, and only `http`, `https` and `mailto` links
+//! become anchors.
+
+use pulldown_cmark::{CodeBlockKind, Event, LinkType, Options, Parser, Tag};
+
+/// Lists and block quotes nested deeper than this render without the extra
+/// levels; their content is kept.
+const MAX_DEPTH: usize = 16;
+
+pub fn to_html(source: &str) -> String {
+    let mut sanitizer = Sanitizer::default();
+    let events = Parser::new_ext(source, Options::ENABLE_STRIKETHROUGH)
+        .filter_map(|event| sanitizer.event(event));
+    let mut out = String::new();
+    pulldown_cmark::html::push_html(&mut out, events);
+    out
+}
+
+/// Escapes text for a double-quoted attribute value.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn safe_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| lower.starts_with(scheme))
+        && !url.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// How the start of a tag was rendered, so its end is rendered to match.
+#[derive(Clone, Copy, PartialEq)]
+enum Open {
+    Dropped,
+    Kept,
+    /// A list or block quote, counted towards [`MAX_DEPTH`].
+    Nested,
+    /// A link, written as raw HTML so it can carry `rel`.
+    Link,
+    /// Raw HTML, shown as a paragraph of text.
+    HtmlBlock,
+}
+
+/// Rewrites parser events into ones that render safely.
+#[derive(Default)]
+struct Sanitizer {
+    open: Vec<Open>,
+    depth: usize,
+}
+
+impl Sanitizer {
+    fn event<'a>(&mut self, event: Event<'a>) -> Option<Event<'a>> {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => match self.open.pop()? {
+                Open::Dropped => None,
+                Open::Kept => Some(Event::End(tag)),
+                Open::Nested => {
+                    self.depth -= 1;
+                    Some(Event::End(tag))
+                }
+                Open::Link => Some(Event::InlineHtml("</a>".into())),
+                Open::HtmlBlock => Some(Event::End(pulldown_cmark::TagEnd::Paragraph)),
+            },
+            Event::Html(html) | Event::InlineHtml(html) => Some(Event::Text(html)),
+            event => Some(event),
+        }
+    }
+
+    fn start<'a>(&mut self, tag: Tag<'a>) -> Option<Event<'a>> {
+        let (open, event) = match tag {
+            Tag::List(_) | Tag::BlockQuote(_) if self.depth >= MAX_DEPTH => (Open::Dropped, None),
+            Tag::List(_) | Tag::BlockQuote(_) => {
+                self.depth += 1;
+                (Open::Nested, Some(Event::Start(tag)))
+            }
+            // Items go wherever their list went.
+            Tag::Item if self.open.last() == Some(&Open::Dropped) => (Open::Dropped, None),
+            Tag::Link {
+                link_type,
+                dest_url,
+                ..
+            } => {
+                let href = match link_type {
+                    LinkType::Email => format!("mailto:{dest_url}"),
+                    _ => dest_url.into_string(),
+                };
+                if safe_url(&href) {
+                    let a = format!(
+                        "<a href=\"{}\" rel=\"nofollow noopener noreferrer\">",
+                        escape(&href)
+                    );
+                    (Open::Link, Some(Event::InlineHtml(a.into())))
+                } else {
+                    (Open::Dropped, None)
+                }
+            }
+            // The alt text inside is rendered as text.
+            Tag::Image { .. } => (Open::Dropped, None),
+            Tag::HtmlBlock => (Open::HtmlBlock, Some(Event::Start(Tag::Paragraph))),
+            Tag::CodeBlock(CodeBlockKind::Fenced(info)) => {
+                let lang: String = info
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or("")
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric() || "_+-".contains(*c))
+                    .collect();
+                let tag = Tag::CodeBlock(CodeBlockKind::Fenced(lang.into()));
+                (Open::Kept, Some(Event::Start(tag)))
+            }
+            tag => (Open::Kept, Some(Event::Start(tag))),
+        };
+        self.open.push(open);
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_the_everyday_subset() {
+        assert_eq!(
+            to_html("# Plan\n\nSome **bold**, _em_ and `code`.\n\n- one\n- two"),
+            "<h1>Plan</h1>\n<p>Some <strong>bold</strong>, <em>em</em> and <code>code</code>.</p>\n\
+             <ul>\n<li>one</li>\n<li>two</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_raw_html() {
+        assert_eq!(
+            to_html("<script>alert('x')</script>"),
+            "<p>&lt;script&gt;alert('x')&lt;/script&gt;</p>\n"
+        );
+        assert_eq!(
+            to_html("<img src=x onerror=alert(1)> & <b>"),
+            "<p>&lt;img src=x onerror=alert(1)&gt; &amp; &lt;b&gt;</p>\n"
+        );
+        assert_eq!(
+            to_html("```\n<script>x</script>\n```"),
+            "<pre><code>&lt;script&gt;x&lt;/script&gt;\n</code></pre>\n"
+        );
+        assert_eq!(to_html("`<b>`"), "<p><code>&lt;b&gt;</code></p>\n");
+    }
+
+    #[test]
+    fn only_links_safe_schemes() {
+        for source in [
+            "[x](javascript:alert(1))",
+            "[x](JaVaScRiPt:alert(1))",
+            "[x](  javascript:alert(1))",
+            "[x](data:text/html;base64,PHNjcmlwdD4=)",
+            "[x](DATA:text/html,x)",
+            "[x](vbscript:x)",
+            "<javascript:alert(1)>",
+            "<Data:text/html,x>",
+        ] {
+            let html = to_html(source);
+            assert!(!html.contains("<a"), "{source} became {html}");
+        }
+        assert_eq!(
+            to_html("[x](HTTPS://example.com)"),
+            "<p><a href=\"HTTPS://example.com\" rel=\"nofollow noopener noreferrer\">x</a></p>\n"
+        );
+        assert_eq!(
+            to_html("<mailto:me@example.com>"),
+            "<p><a href=\"mailto:me@example.com\" rel=\"nofollow noopener noreferrer\">\
+             mailto:me@example.com</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_quotes_in_urls_titles_and_info_strings() {
+        assert_eq!(
+            to_html("[x](http://e.com/\"onmouseover=\"alert(1))"),
+            "<p><a href=\"http://e.com/&quot;onmouseover=&quot;alert(1)\" \
+             rel=\"nofollow noopener noreferrer\">x</a></p>\n"
+        );
+        // Not a link in CommonMark, so it stays text.
+        assert_eq!(
+            to_html("[x](http://e.com/'a' \"title\" onclick=\"y\")"),
+            "<p>[x](http://e.com/'a' \"title\" onclick=\"y\")</p>\n"
+        );
+        assert_eq!(
+            to_html("[x](http://e.com/'a' \"title\")"),
+            "<p><a href=\"http://e.com/&#39;a&#39;\" rel=\"nofollow noopener noreferrer\">x</a></p>\n"
+        );
+        assert_eq!(
+            to_html("```rust\" onmouseover=\"alert(1)\nfn main() {}\n```"),
+            "<pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n"
+        );
+        assert_eq!(
+            to_html("```\"><script>\nx\n```"),
+            "<pre><code>x\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn deeply_nested_quotes_stop_at_max_depth() {
+        let source = ">".repeat(10_000) + " deep";
+        let html = to_html(&source);
+        assert_eq!(html.matches("<blockquote>").count(), MAX_DEPTH);
+        assert_eq!(html.matches("</blockquote>").count(), MAX_DEPTH);
+        assert!(html.contains("deep"));
+    }
+
+    #[test]
+    fn deeply_nested_lists_stop_at_max_depth() {
+        let source: String = (0..200)
+            .map(|n| format!("{}- item {n}\n", "  ".repeat(n)))
+            .collect();
+        let html = to_html(&source);
+        assert_eq!(html.matches("<ul>").count(), MAX_DEPTH);
+        assert_eq!(html.matches("</ul>").count(), MAX_DEPTH);
+        assert!(html.contains("item 199"));
+
+        let source = "- ".repeat(5_000) + "x";
+        let html = to_html(&source);
+        assert!(html.matches("<ul>").count() <= MAX_DEPTH);
+        assert_eq!(html.matches("<ul>").count(), html.matches("</ul>").count());
+    }
+
+    #[test]
+    fn continues_list_items_without_panicking() {
+        assert_eq!(
+            to_html("- one\ncontinued\n  indented\n\n  more"),
+            "<ul>\n<li>\n<p>one\ncontinued\nindented</p>\n<p>more</p>\n</li>\n</ul>\n"
+        );
+        for source in ["-\n  x", "1.\nlazy", "- \n\n\n  x", "  - a\n - b\n   c"] {
+            to_html(source);
+        }
+    }
+
+    #[test]
+    fn shows_images_and_inline_html_as_text() {
+        assert_eq!(
+            to_html("![a cat](http://e.com/cat.png) <a href=\"x\">y</a>"),
+            "<p>a cat &lt;a href=\"x\"&gt;y&lt;/a&gt;</p>\n"
+        );
+        assert_eq!(
+            to_html("<div onclick=\"x\">\n*hi*\n</div>"),
+            "<p>&lt;div onclick=\"x\"&gt;\n*hi*\n&lt;/div&gt;</p>\n"
+        );
+    }
+
+    #[test]
+    fn long_runs_of_delimiters_render() {
+        for source in ["*a".repeat(50_000), "[".repeat(50_000), "`a".repeat(50_000)] {
+            to_html(&source);
+        }
+    }
+}
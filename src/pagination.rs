@@ -0,0 +1,357 @@
+//! Relay cursor connections for `todosConnection`.
+//!
+//! Cursors are opaque and hold the sort key of a todo rather than an offset
+//! or a bare id: the values of the fields the list is ordered by, plus the
+//! creation time and id that break ties, and nothing else. A page resumes
+//! right after (or before) that key, so pages neither shift nor break when
+//! todos are inserted or deleted between requests, including the todo the
+//! cursor was taken from. A cursor is only valid with the `orderBy` it was
+//! issued under.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use juniper::GraphQLObject;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use crate::error::{AppError, AppResult};
+use crate::filter::{self, TodoOrderBy, TodoSortField};
+use crate::{Context, Todo};
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct TodoEdge {
+    pub cursor: String,
+    pub node: Todo,
+}
+
+#[derive(GraphQLObject)]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct TodoConnection {
+    pub edges: Vec<TodoEdge>,
+    pub page_info: PageInfo,
+    /// Number of todos across all pages.
+    pub total_count: i32,
+}
+
+/// Arguments of a Relay connection field.
+pub struct PageArgs {
+    pub first: Option<i32>,
+    pub after: Option<String>,
+    pub last: Option<i32>,
+    pub before: Option<String>,
+}
+
+/// The value `field` sorts `todo` by. Titles compare case-insensitively, so
+/// only the lowercased title is needed.
+fn sort_value(field: TodoSortField, todo: &Todo) -> serde_json::Result<Value> {
+    match field {
+        TodoSortField::Title => serde_json::to_value(todo.title.to_lowercase()),
+        TodoSortField::Completed => serde_json::to_value(todo.completed),
+        TodoSortField::CreatedAt => serde_json::to_value(todo.created_at),
+        TodoSortField::UpdatedAt => serde_json::to_value(todo.updated_at),
+        TodoSortField::CompletedAt => serde_json::to_value(todo.completed_at),
+        TodoSortField::DueAt => serde_json::to_value(todo.due_at),
+        TodoSortField::Priority => serde_json::to_value(todo.priority),
+        TodoSortField::Position => serde_json::to_value(&todo.position),
+    }
+}
+
+/// The inverse of [`sort_value`], applied to a stand-in todo.
+fn set_sort_value(field: TodoSortField, todo: &mut Todo, value: Value) -> serde_json::Result<()> {
+    match field {
+        TodoSortField::Title => todo.title = serde_json::from_value(value)?,
+        TodoSortField::Completed => todo.completed = serde_json::from_value(value)?,
+        TodoSortField::CreatedAt => todo.created_at = serde_json::from_value(value)?,
+        TodoSortField::UpdatedAt => todo.updated_at = serde_json::from_value(value)?,
+        TodoSortField::CompletedAt => todo.completed_at = serde_json::from_value(value)?,
+        TodoSortField::DueAt => todo.due_at = serde_json::from_value(value)?,
+        TodoSortField::Priority => todo.priority = serde_json::from_value(value)?,
+        TodoSortField::Position => todo.position = serde_json::from_value(value)?,
+    }
+    Ok(())
+}
+
+/// The values [`filter::compare`] looks at under `order_by`, in order,
+/// followed by the creation time and id that break ties.
+fn encode_cursor(order_by: &[TodoOrderBy], todo: &Todo) -> String {
+    let key: serde_json::Result<Vec<Value>> = filter::effective(order_by)
+        .iter()
+        .map(|o| sort_value(o.field, todo))
+        .chain([
+            serde_json::to_value(todo.created_at),
+            serde_json::to_value(&todo.id),
+        ])
+        .collect();
+    let key = key
+        .and_then(|key| serde_json::to_vec(&key))
+        .unwrap_or_default();
+    URL_SAFE_NO_PAD.encode(key)
+}
+
+/// A stand-in todo that sorts exactly where the keyed todo did under
+/// `order_by`.
+fn decode_cursor(order_by: &[TodoOrderBy], field: &str, cursor: &str) -> AppResult<Todo> {
+    let malformed = || AppError::invalid(field, "malformed cursor");
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| malformed())?;
+    let key: Vec<Value> = serde_json::from_slice(&bytes).map_err(|_| malformed())?;
+    let order_by = filter::effective(order_by);
+    let [values @ .., created_at, id] = &key[..] else {
+        return Err(malformed());
+    };
+    if values.len() != order_by.len() {
+        return Err(malformed());
+    }
+    let mut todo = Todo::new(String::new());
+    for (o, value) in order_by.iter().zip(values) {
+        set_sort_value(o.field, &mut todo, value.clone()).map_err(|_| malformed())?;
+    }
+    todo.created_at = serde_json::from_value(created_at.clone()).map_err(|_| malformed())?;
+    todo.id = serde_json::from_value(id.clone()).map_err(|_| malformed())?;
+    Ok(todo)
+}
+
+fn page_size(field: &str, n: Option<i32>) -> AppResult<Option<usize>> {
+    match n {
+        Some(n) if n < 0 => Err(AppError::invalid(field, "must not be negative")),
+        n => Ok(n.map(|n| n as usize)),
+    }
+}
+
+/// Slices `todos`, sorted by `order_by`, according to the Relay cursor
+/// connection spec.
+pub fn paginate(
+    todos: Vec<Todo>,
+    order_by: &[TodoOrderBy],
+    args: PageArgs,
+) -> AppResult<TodoConnection> {
+    let first = page_size("first", args.first)?;
+    let last = page_size("last", args.last)?;
+
+    let total = todos.len();
+    let mut start = 0;
+    let mut end = total;
+    if let Some(after) = &args.after {
+        let key = decode_cursor(order_by, "after", after)?;
+        start = todos.partition_point(|t| filter::compare(order_by, t, &key) != Ordering::Greater);
+    }
+    if let Some(before) = &args.before {
+        let key = decode_cursor(order_by, "before", before)?;
+        end = todos.partition_point(|t| filter::compare(order_by, t, &key) == Ordering::Less);
+    }
+    end = end.max(start);
+    if let Some(first) = first {
+        end = end.min(start + first);
+    }
+    if let Some(last) = last {
+        start = start.max(end.saturating_sub(last));
+    }
+
+    let edges: Vec<TodoEdge> = todos
+        .into_iter()
+        .skip(start)
+        .take(end - start)
+        .map(|node| TodoEdge {
+            cursor: encode_cursor(order_by, &node),
+            node,
+        })
+        .collect();
+
+    Ok(TodoConnection {
+        page_info: PageInfo {
+            has_next_page: end < total,
+            has_previous_page: start > 0,
+            start_cursor: edges.first().map(|e| e.cursor.clone()),
+            end_cursor: edges.last().map(|e| e.cursor.clone()),
+        },
+        edges,
+        total_count: total as i32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::SortDirection;
+    use crate::todo::Priority;
+
+    fn todos(n: usize) -> Vec<Todo> {
+        let mut todos: Vec<Todo> = (0..n)
+            .map(|i| {
+                let mut todo = Todo::new(format!("todo {i}"));
+                todo.position = format!("a{i}");
+                todo.created_at += chrono::Duration::seconds(i as i64);
+                todo
+            })
+            .collect();
+        filter::apply(&mut todos, None, &[]);
+        todos
+    }
+
+    fn titles(page: &TodoConnection) -> Vec<&str> {
+        page.edges.iter().map(|e| e.node.title.as_str()).collect()
+    }
+
+    fn args(first: Option<i32>, after: Option<String>) -> PageArgs {
+        PageArgs {
+            first,
+            after,
+            last: None,
+            before: None,
+        }
+    }
+
+    #[test]
+    fn pages_forward_through_all_todos() {
+        let all = todos(5);
+        let page = paginate(all.clone(), &[], args(Some(2), None)).unwrap();
+        assert_eq!(titles(&page), ["todo 0", "todo 1"]);
+        assert!(page.page_info.has_next_page);
+        assert!(!page.page_info.has_previous_page);
+
+        let page = paginate(all.clone(), &[], args(Some(2), page.page_info.end_cursor)).unwrap();
+        assert_eq!(titles(&page), ["todo 2", "todo 3"]);
+
+        let page = paginate(all, &[], args(Some(2), page.page_info.end_cursor)).unwrap();
+        assert_eq!(titles(&page), ["todo 4"]);
+        assert!(!page.page_info.has_next_page);
+        assert!(page.page_info.has_previous_page);
+        assert_eq!(page.total_count, 5);
+    }
+
+    #[test]
+    fn pages_backward_with_last_and_before() {
+        let all = todos(5);
+        let page = paginate(
+            all.clone(),
+            &[],
+            PageArgs {
+                first: None,
+                after: None,
+                last: Some(2),
+                before: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(titles(&page), ["todo 3", "todo 4"]);
+
+        let page = paginate(
+            all,
+            &[],
+            PageArgs {
+                first: None,
+                after: None,
+                last: Some(2),
+                before: page.page_info.start_cursor,
+            },
+        )
+        .unwrap();
+        assert_eq!(titles(&page), ["todo 1", "todo 2"]);
+    }
+
+    #[test]
+    fn resumes_after_a_deleted_todo() {
+        let mut all = todos(5);
+        let page = paginate(all.clone(), &[], args(Some(2), None)).unwrap();
+        all.retain(|t| t.title != "todo 1");
+        let page = paginate(all, &[], args(Some(2), page.page_info.end_cursor)).unwrap();
+        assert_eq!(titles(&page), ["todo 2", "todo 3"]);
+    }
+
+    #[test]
+    fn resumes_before_a_deleted_todo() {
+        let mut all = todos(5);
+        let before = paginate(all.clone(), &[], args(Some(3), None))
+            .unwrap()
+            .page_info
+            .end_cursor;
+        all.retain(|t| t.title != "todo 2");
+        let page = paginate(
+            all,
+            &[],
+            PageArgs {
+                first: None,
+                after: None,
+                last: None,
+                before,
+            },
+        )
+        .unwrap();
+        assert_eq!(titles(&page), ["todo 0", "todo 1"]);
+    }
+
+    #[test]
+    fn resumes_under_a_custom_order_with_ties() {
+        let order_by = [TodoOrderBy {
+            field: TodoSortField::Priority,
+            direction: SortDirection::Desc,
+        }];
+        let mut all = todos(4);
+        all[1].priority = Priority::High;
+        filter::apply(&mut all, None, &order_by);
+        let page = paginate(all.clone(), &order_by, args(Some(2), None)).unwrap();
+        assert_eq!(titles(&page), ["todo 1", "todo 0"]);
+        let page = paginate(all, &order_by, args(Some(2), page.page_info.end_cursor)).unwrap();
+        assert_eq!(titles(&page), ["todo 2", "todo 3"]);
+    }
+
+    #[test]
+    fn cursors_hold_only_the_ordered_fields() {
+        let mut todo = Todo::new("Secret Plans".into());
+        todo.notes = Some("private".into());
+        let decode = |cursor: String| -> Vec<Value> {
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(cursor).unwrap()).unwrap()
+        };
+
+        let key = decode(encode_cursor(&[], &todo));
+        // Completed, priority, due date and position, then the tie-breakers.
+        assert_eq!(key.len(), 6);
+        assert!(!key.contains(&Value::from("Secret Plans")));
+        assert_eq!(key[5], Value::from(todo.id.clone()));
+
+        let order_by = [TodoOrderBy {
+            field: TodoSortField::Title,
+            direction: SortDirection::Asc,
+        }];
+        let key = decode(encode_cursor(&order_by, &todo));
+        assert_eq!(key[0], Value::from("secret plans"));
+        assert_eq!(key.len(), 3);
+        assert!(!serde_json::to_string(&key).unwrap().contains("private"));
+    }
+
+    #[test]
+    fn rejects_cursors_from_another_order() {
+        let all = todos(3);
+        let order_by = [TodoOrderBy {
+            field: TodoSortField::Title,
+            direction: SortDirection::Asc,
+        }];
+        let cursor = paginate(all.clone(), &order_by, args(Some(1), None))
+            .unwrap()
+            .page_info
+            .end_cursor;
+        assert!(paginate(all, &[], args(Some(1), cursor)).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_cursors_and_negative_sizes() {
+        assert!(paginate(todos(1), &[], args(None, Some("nope".into()))).is_err());
+        assert!(
+            paginate(
+                todos(1),
+                &[],
+                args(None, Some(URL_SAFE_NO_PAD.encode("todo:x")))
+            )
+            .is_err()
+        );
+        assert!(paginate(todos(1), &[], args(Some(-1), None)).is_err());
+    }
+}
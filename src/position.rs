@@ -0,0 +1,338 @@
+//! Fractional indexing for manual ordering.
+//!
+//! Positions are strings that sort lexicographically, and there is always
+//! room for another one between any two, so moving a todo only rewrites its
+//! own position, never its siblings'. This follows the scheme used by the
+//! `fractional-indexing` JS package: a variable-length integer part (the head
+//! character encodes its length, so appending keeps keys short) followed by a
+//! base-62 fraction. The fraction digit picked inside a gap is random, so two
+//! clients dropping todos into the same gap at once almost always end up with
+//! distinct positions.
+
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::store::{StoreResult, TodoStore};
+
+const DIGITS: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const ZERO: u8 = DIGITS[0];
+const MAX: u8 = DIGITS[61];
+
+fn digit(c: u8) -> Option<usize> {
+    DIGITS.iter().position(|&d| d == c)
+}
+
+/// A random index in `low + 1..high`; `high - low` must be at least 2.
+fn pick(low: usize, high: usize) -> usize {
+    // uuid's v4 generator is our source of randomness.
+    let r = Uuid::new_v4().as_u128();
+    low + 1 + (r % (high - low - 1) as u128) as usize
+}
+
+/// Length of the integer part starting with `head`: `a`..`z` for
+/// non-negative integers with 1 to 26 digits, `Z`..`A` for negative ones.
+fn integer_len(head: u8) -> Option<usize> {
+    match head {
+        b'a'..=b'z' => Some((head - b'a') as usize + 2),
+        b'A'..=b'Z' => Some((b'Z' - head) as usize + 2),
+        _ => None,
+    }
+}
+
+/// Splits a position into its integer and fraction parts.
+fn split(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = integer_len(*key.first()?)?;
+    let (int, frac) = key.split_at_checked(len)?;
+    let valid =
+        int[1..].iter().chain(frac).all(|&c| digit(c).is_some()) && frac.last() != Some(&ZERO);
+    valid.then_some((int, frac))
+}
+
+fn increment(int: &[u8]) -> Option<Vec<u8>> {
+    let (head, digits) = int.split_first()?;
+    let mut digits = digits.to_vec();
+    for d in digits.iter_mut().rev() {
+        if *d != MAX {
+            *d = DIGITS[digit(*d)? + 1];
+            return Some([&[*head], &digits[..]].concat());
+        }
+        *d = ZERO;
+    }
+    // Carried out of every digit: move on to the next integer length.
+    match head {
+        b'Z' => Some(vec![b'a', ZERO]),
+        b'z' => None,
+        _ => {
+            let head = head + 1;
+            if head > b'a' {
+                digits.push(ZERO);
+            } else {
+                digits.pop();
+            }
+            Some([&[head], &digits[..]].concat())
+        }
+    }
+}
+
+fn decrement(int: &[u8]) -> Option<Vec<u8>> {
+    let (head, digits) = int.split_first()?;
+    let mut digits = digits.to_vec();
+    for d in digits.iter_mut().rev() {
+        if *d != ZERO {
+            *d = DIGITS[digit(*d)? - 1];
+            return Some([&[*head], &digits[..]].concat());
+        }
+        *d = MAX;
+    }
+    match head {
+        b'a' => Some(vec![b'Z', MAX]),
+        b'A' => None,
+        _ => {
+            let head = head - 1;
+            if head < b'Z' {
+                digits.push(MAX);
+            } else {
+                digits.pop();
+            }
+            Some([&[head], &digits[..]].concat())
+        }
+    }
+}
+
+/// A fraction strictly between `low` and `high` (unbounded if `None`).
+/// Requires `low < high`, and neither may end in a zero digit.
+fn midpoint(low: &[u8], high: Option<&[u8]>) -> Vec<u8> {
+    if let Some(high) = high {
+        // Copy the shared prefix, treating missing digits of `low` as zeros.
+        let n = high
+            .iter()
+            .enumerate()
+            .take_while(|&(i, &c)| low.get(i).copied().unwrap_or(ZERO) == c)
+            .count();
+        if n > 0 {
+            let rest = midpoint(low.get(n..).unwrap_or_default(), Some(&high[n..]));
+            return [&high[..n], &rest[..]].concat();
+        }
+    }
+    let a = low.first().and_then(|&c| digit(c)).unwrap_or(0);
+    let b = high
+        .and_then(|h| h.first())
+        .and_then(|&c| digit(c))
+        .unwrap_or(DIGITS.len());
+    if b - a > 1 {
+        return vec![DIGITS[pick(a, b)]];
+    }
+    match high {
+        // `high`'s first digit alone sorts below `high` and above `low`.
+        Some(high) if high.len() > 1 => vec![high[0]],
+        _ => {
+            let rest = midpoint(low.get(1..).unwrap_or_default(), None);
+            [&[DIGITS[a]], &rest[..]].concat()
+        }
+    }
+}
+
+/// A position strictly between `low` and `high`; `None` means unbounded.
+/// Returns `None` if `low` isn't below `high` or either isn't a valid
+/// position.
+pub fn between(low: Option<&str>, high: Option<&str>) -> Option<String> {
+    if let (Some(low), Some(high)) = (low, high)
+        && low >= high
+    {
+        return None;
+    }
+    let low = match low {
+        Some(key) => Some(split(key.as_bytes())?),
+        None => None,
+    };
+    let high = match high {
+        Some(key) => Some(split(key.as_bytes())?),
+        None => None,
+    };
+    let key = match (low, high) {
+        (None, None) => [&b"a0"[..], &midpoint(b"", None)].concat(),
+        (None, Some((ib, fb))) => match decrement(ib) {
+            Some(i) => [&i[..], &midpoint(b"", None)].concat(),
+            None if !fb.is_empty() => [ib, &midpoint(b"", Some(fb))].concat(),
+            None => return None,
+        },
+        (Some((ia, fa)), None) => match increment(ia) {
+            Some(i) => [&i[..], &midpoint(b"", None)].concat(),
+            None => [ia, &midpoint(fa, None)].concat(),
+        },
+        (Some((ia, fa)), Some((ib, fb))) => {
+            if ia == ib {
+                [ia, &midpoint(fa, Some(fb))].concat()
+            } else {
+                let i = increment(ia)?;
+                if i[..] < *ib {
+                    [&i[..], &midpoint(b"", None)].concat()
+                } else if !fb.is_empty() {
+                    // `i` is `high`'s integer part.
+                    [&i[..], &midpoint(b"", Some(fb))].concat()
+                } else {
+                    [ia, &midpoint(fa, None)].concat()
+                }
+            }
+        }
+    };
+    String::from_utf8(key).ok()
+}
+
+/// The new position of item `id` for a `reorder*` mutation: right after
+/// `before_id` and right before `after_id`. With only one of them, the item
+/// goes directly next to it. `others` holds `(id, position)` of every other
+/// item in the same ordering; `kind` names them in not-found errors.
+pub fn reorder(
+    kind: &'static str,
+    id: &str,
+    others: &[(&str, &str)],
+    before_id: Option<&str>,
+    after_id: Option<&str>,
+) -> AppResult<String> {
+    if before_id.is_none() && after_id.is_none() {
+        return Err(AppError::invalid(
+            "beforeId",
+            "give beforeId, afterId or both",
+        ));
+    }
+    let find = |field: &str, other_id: Option<&str>| -> AppResult<Option<&str>> {
+        let Some(other_id) = other_id else {
+            return Ok(None);
+        };
+        if other_id == id {
+            return Err(AppError::invalid(field, "must differ from id"));
+        }
+        let &(_, position) = others
+            .iter()
+            .find(|(other, _)| *other == other_id)
+            .ok_or_else(|| AppError::not_found(kind, other_id))?;
+        Ok(Some(position))
+    };
+    let mut low = find("beforeId", before_id)?;
+    let mut high = find("afterId", after_id)?;
+    let positions = others.iter().map(|&(_, p)| p);
+    match (low, high) {
+        (Some(l), None) => high = positions.filter(|&p| p > l).min(),
+        (None, Some(h)) => low = positions.filter(|&p| p < h).max(),
+        _ => {}
+    }
+    between(low, high).ok_or_else(|| AppError::invalid("afterId", "must come after beforeId"))
+}
+
+/// A position after every todo in the store.
+pub fn last(store: &dyn TodoStore) -> StoreResult<String> {
+    let todos = store.todos().list()?;
+    let max = todos
+        .iter()
+        .map(|t| t.position.as_str())
+        .filter(|p| !p.is_empty())
+        .max();
+    Ok(between(max, None).unwrap_or_default())
+}
+
+/// Gives todos saved before manual ordering existed a position after all
+/// others, keeping their store order.
+pub fn backfill(store: &dyn TodoStore) -> StoreResult<()> {
+    for todo in store.todos().list()? {
+        if todo.position.is_empty() {
+            let position = last(store)?;
+            store
+                .todos()
+                .update(&todo.id, &mut |t| t.position = position.clone())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ordered(keys: &[String]) -> bool {
+        keys.windows(2).all(|w| w[0] < w[1])
+    }
+
+    #[test]
+    fn first_key_is_in_the_zero_integer() {
+        let key = between(None, None).unwrap();
+        assert!(key.starts_with("a0"));
+        assert!(split(key.as_bytes()).is_some());
+    }
+
+    #[test]
+    fn appending_and_prepending_stay_ordered_and_short() {
+        let mut keys = vec![between(None, None).unwrap()];
+        for _ in 0..500 {
+            keys.push(between(keys.last().map(String::as_str), None).unwrap());
+        }
+        for _ in 0..500 {
+            keys.insert(0, between(None, Some(&keys[0])).unwrap());
+        }
+        assert!(ordered(&keys));
+        assert!(keys.iter().all(|k| k.len() <= 5), "{keys:?}");
+    }
+
+    #[test]
+    fn repeated_insertion_into_one_gap_stays_ordered() {
+        let low = between(None, None).unwrap();
+        let mut high = between(Some(&low), None).unwrap();
+        for _ in 0..200 {
+            let mid = between(Some(&low), Some(&high)).unwrap();
+            assert!(low < mid && mid < high, "{low} < {mid} < {high}");
+            assert!(split(mid.as_bytes()).is_some(), "{mid}");
+            high = mid;
+        }
+        let mut low = low;
+        for _ in 0..200 {
+            let mid = between(Some(&low), Some(&high)).unwrap();
+            assert!(low < mid && mid < high, "{low} < {mid} < {high}");
+            low = mid;
+        }
+    }
+
+    #[test]
+    fn carries_across_integer_lengths() {
+        assert_eq!(increment(b"a0").unwrap(), b"a1");
+        assert_eq!(increment(b"az").unwrap(), b"b00");
+        assert_eq!(increment(b"Zz").unwrap(), b"a0");
+        assert_eq!(decrement(b"a0").unwrap(), b"Zz");
+        assert_eq!(decrement(b"b00").unwrap(), b"az");
+        assert!(between(Some("Zz"), Some("a0")).is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_bounds() {
+        assert_eq!(between(Some("a1"), Some("a0")), None);
+        assert_eq!(between(Some("a1"), Some("a1")), None);
+        assert_eq!(between(Some("!"), None), None);
+        // A fraction may not end in a zero digit.
+        assert_eq!(between(Some("a00"), None), None);
+    }
+
+    #[test]
+    fn reorder_places_next_to_the_given_neighbours() {
+        let others = [("a", "a1"), ("b", "a3"), ("c", "a5")];
+        let after_a = reorder("todo", "x", &others, Some("a"), None).unwrap();
+        assert!("a1" < after_a.as_str() && after_a.as_str() < "a3");
+        let before_c = reorder("todo", "x", &others, None, Some("c")).unwrap();
+        assert!("a3" < before_c.as_str() && before_c.as_str() < "a5");
+        let last = reorder("todo", "x", &others, Some("c"), None).unwrap();
+        assert!(last.as_str() > "a5");
+        let between_ab = reorder("todo", "x", &others, Some("a"), Some("b")).unwrap();
+        assert!("a1" < between_ab.as_str() && between_ab.as_str() < "a3");
+    }
+
+    #[test]
+    fn reorder_reports_bad_neighbours() {
+        let others = [("a", "a1"), ("b", "a3")];
+        let invalid = |r: AppResult<String>| matches!(r, Err(AppError::Invalid(_)));
+        assert!(invalid(reorder("todo", "x", &others, None, None)));
+        assert!(invalid(reorder("todo", "x", &others, Some("x"), None)));
+        assert!(invalid(reorder("todo", "x", &others, Some("b"), Some("a"))));
+        assert!(matches!(
+            reorder("todo", "x", &others, Some("gone"), None),
+            Err(AppError::NotFound { kind: "todo", .. })
+        ));
+    }
+}
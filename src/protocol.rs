@@ -0,0 +1,205 @@
+//! GraphQL-over-HTTP rules for the `/graphql` endpoint: response media types
+//! and status codes, and which operations may be sent with `GET`.
+
+use axum::Json;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use juniper::http::{GraphQLRequest, GraphQLResponse};
+use juniper::{Definition, OperationType};
+
+use crate::Schema;
+
+pub const GRAPHQL_RESPONSE_JSON: &str = "application/graphql-response+json";
+
+/// The media type a response is sent as.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `application/graphql-response+json`: status codes tell request errors
+    /// apart from execution errors.
+    GraphQLResponse,
+    /// Legacy `application/json`: every well-formed request gets a `200`.
+    Json,
+}
+
+impl Format {
+    /// Picks the format from the `Accept` header, preferring
+    /// `application/graphql-response+json`. Clients that send no `Accept`
+    /// header get `application/json`; `None` means nothing acceptable was
+    /// offered.
+    pub fn negotiate(headers: &HeaderMap) -> Option<Format> {
+        let Some(accept) = headers.get(header::ACCEPT) else {
+            return Some(Format::Json);
+        };
+        let accept = accept.to_str().ok()?;
+        // Media types with `q=0` are explicitly refused.
+        let types: Vec<String> = accept
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let media_type = parts.next()?.trim().to_ascii_lowercase();
+                let refused = parts.any(|p| {
+                    p.trim()
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        == Some(0.0)
+                });
+                (!refused).then_some(media_type)
+            })
+            .collect();
+        if types.iter().any(|t| t == GRAPHQL_RESPONSE_JSON) {
+            Some(Format::GraphQLResponse)
+        } else if types
+            .iter()
+            .any(|t| matches!(t.as_str(), "application/json" | "application/*" | "*/*"))
+        {
+            Some(Format::Json)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(self) -> HeaderValue {
+        match self {
+            Format::GraphQLResponse => {
+                HeaderValue::from_static("application/graphql-response+json; charset=utf-8")
+            }
+            Format::Json => HeaderValue::from_static("application/json; charset=utf-8"),
+        }
+    }
+
+    /// Status code for a response: errors that stopped the request before
+    /// execution (parse and validation errors, an unknown operation) are a
+    /// `400` in the GraphQL response format.
+    pub fn status(self, res: &GraphQLResponse) -> StatusCode {
+        match self {
+            Format::GraphQLResponse if !res.is_ok() => StatusCode::BAD_REQUEST,
+            _ => StatusCode::OK,
+        }
+    }
+
+    pub fn respond(self, res: GraphQLResponse) -> Response {
+        (
+            self.status(&res),
+            [(header::CONTENT_TYPE, self.content_type())],
+            Json(res),
+        )
+            .into_response()
+    }
+
+    /// Responses to a batch are a `200` either way, since each operation
+    /// reports its own errors.
+    pub fn respond_batch(self, res: Vec<GraphQLResponse>) -> Response {
+        (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, self.content_type())],
+            Json(res),
+        )
+            .into_response()
+    }
+}
+
+/// The type of the operation `req` would run, or `None` if the document
+/// doesn't parse or doesn't single one out; execution reports those errors.
+pub fn operation_type(schema: &Schema, req: &GraphQLRequest) -> Option<OperationType> {
+    let document = juniper::parser::parse_document_source(&req.query, &schema.schema).ok()?;
+    let mut operations = document.iter().filter_map(|d| match d {
+        Definition::Operation(op) => Some(&op.item),
+        Definition::Fragment(_) => None,
+    });
+    let operation = match &req.operation_name {
+        Some(name) => operations.find(|op| op.name.as_ref().is_some_and(|n| n.item == name))?,
+        None => {
+            let first = operations.next()?;
+            if operations.next().is_some() {
+                return None;
+            }
+            first
+        }
+    };
+    Some(operation.operation_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use juniper::{FieldError, GraphQLError, Value};
+
+    use crate::{MutationRoot, QueryRoot, SubscriptionRoot};
+
+    fn accept(value: &str) -> Option<Format> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        Format::negotiate(&headers)
+    }
+
+    fn request(query: &str, operation_name: Option<&str>) -> GraphQLRequest {
+        GraphQLRequest::new(query.into(), operation_name.map(Into::into), None)
+    }
+
+    #[test]
+    fn negotiates_the_response_format() {
+        assert!(Format::negotiate(&HeaderMap::new()) == Some(Format::Json));
+        assert!(accept("application/json") == Some(Format::Json));
+        assert!(accept("*/*") == Some(Format::Json));
+        assert!(accept("application/*;q=0.5") == Some(Format::Json));
+        assert!(
+            accept("application/json, application/graphql-response+json")
+                == Some(Format::GraphQLResponse)
+        );
+        assert!(
+            accept("Application/GraphQL-Response+JSON; charset=utf-8")
+                == Some(Format::GraphQLResponse)
+        );
+    }
+
+    #[test]
+    fn respects_refused_and_unknown_media_types() {
+        assert!(
+            accept("application/graphql-response+json;q=0, application/json") == Some(Format::Json)
+        );
+        assert!(accept("application/json; q=0").is_none());
+        assert!(accept("*/*;q=0.0").is_none());
+        assert!(accept("text/html, image/png").is_none());
+    }
+
+    #[test]
+    fn only_pre_execution_errors_are_bad_requests() {
+        let parse_failed = || GraphQLResponse::from_result(Err(GraphQLError::UnknownOperationName));
+        let field_failed = || GraphQLResponse::error(FieldError::new("boom", Value::null()));
+        let ok = || GraphQLResponse::from_result(Ok((Value::null(), Vec::new())));
+
+        let format = Format::GraphQLResponse;
+        assert_eq!(format.status(&parse_failed()), StatusCode::BAD_REQUEST);
+        assert_eq!(format.status(&field_failed()), StatusCode::OK);
+        assert_eq!(format.status(&ok()), StatusCode::OK);
+        let format = Format::Json;
+        assert_eq!(format.status(&parse_failed()), StatusCode::OK);
+        assert_eq!(format.status(&field_failed()), StatusCode::OK);
+    }
+
+    #[test]
+    fn finds_the_operation_type() {
+        let schema = Schema::new(QueryRoot, MutationRoot, SubscriptionRoot);
+        let op = |query: &str, name: Option<&str>| operation_type(&schema, &request(query, name));
+
+        assert_eq!(op("{ todos { id } }", None), Some(OperationType::Query));
+        assert_eq!(
+            op("mutation { deleteTodo(id: \"x\") }", None),
+            Some(OperationType::Mutation)
+        );
+        let both = "query Read { todos { id } } mutation Write { deleteTodo(id: \"x\") }";
+        assert_eq!(op(both, Some("Read")), Some(OperationType::Query));
+        assert_eq!(op(both, Some("Write")), Some(OperationType::Mutation));
+        assert_eq!(op(both, Some("Other")), None);
+        assert_eq!(op(both, None), None);
+        assert_eq!(
+            op("{ todos { id } } mutation { deleteTodo(id: \"x\") }", None),
+            None
+        );
+        assert_eq!(
+            op("fragment F on Todo { id } { todos { ...F } }", None),
+            Some(OperationType::Query)
+        );
+        assert_eq!(op("{ todos {", None), None);
+    }
+}
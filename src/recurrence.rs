@@ -0,0 +1,600 @@
+//! Repeating todos described by a subset of iCalendar `RRULE`s (RFC 5545).
+//!
+//! Supported: `FREQ` of `DAILY`, `WEEKLY`, `MONTHLY` or `YEARLY`, plus
+//! `INTERVAL`, `COUNT`, `UNTIL`, `BYDAY` (with ordinals such as `2TU` or
+//! `-1FR` for monthly rules) and `BYMONTHDAY` (monthly rules only). Any other
+//! part, such as `BYSETPOS`, `BYHOUR` or a `WKST` other than `MO`, is
+//! rejected rather than ignored, as are repeated parts. Rules are
+//! evaluated on the wall clock of the series' time zone, so a daily 09:00
+//! todo stays at 09:00 across DST changes, and the series start always counts
+//! as the first occurrence.
+
+use chrono::{
+    DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rule {
+    freq: Freq,
+    interval: u32,
+    /// `(ordinal, weekday)`; the ordinal picks e.g. the 2nd or last weekday
+    /// of the month.
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    count: Option<u32>,
+    until: Option<Until>,
+}
+
+/// Last possible occurrence of a series.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Until {
+    /// `UNTIL=...Z`: an instant.
+    Utc(DateTime<Utc>),
+    /// A date or a time without `Z`: wall-clock time in the series' zone.
+    Local(NaiveDateTime),
+}
+
+/// A rule plus the instant its series started (`DTSTART`) and the time zone
+/// it is evaluated in.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recurrence {
+    pub rule: Rule,
+    pub start: DateTime<Utc>,
+    /// Series saved before zones were supported run in UTC.
+    #[serde(default = "utc")]
+    pub time_zone: Tz,
+}
+
+fn utc() -> Tz {
+    Tz::UTC
+}
+
+/// A rule stops producing occurrences after this many periods in a row
+/// without one, e.g. `BYMONTHDAY=30` on a yearly February start.
+const MAX_EMPTY_PERIODS: u32 = 1000;
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("MO", Weekday::Mon),
+    ("TU", Weekday::Tue),
+    ("WE", Weekday::Wed),
+    ("TH", Weekday::Thu),
+    ("FR", Weekday::Fri),
+    ("SA", Weekday::Sat),
+    ("SU", Weekday::Sun),
+];
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix("RRULE:").unwrap_or(s);
+        let mut freq = None;
+        let mut rule = Rule {
+            freq: Freq::Daily,
+            interval: 1,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            count: None,
+            until: None,
+        };
+        let mut seen = Vec::new();
+        for part in s.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got {part:?}"))?;
+            let key = key.to_ascii_uppercase();
+            if seen.contains(&key) {
+                return Err(format!("{key} given more than once"));
+            }
+            seen.push(key.clone());
+            match key.as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Freq::Daily,
+                        "WEEKLY" => Freq::Weekly,
+                        "MONTHLY" => Freq::Monthly,
+                        "YEARLY" => Freq::Yearly,
+                        _ => return Err(format!("unsupported FREQ {value:?}")),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or("INTERVAL must be a positive integer")?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or("COUNT must be a positive integer")?,
+                    )
+                }
+                "UNTIL" => rule.until = Some(parse_until(value)?),
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(parse_by_day)
+                        .collect::<Result<_, _>>()?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = value
+                        .split(',')
+                        .map(|d| {
+                            d.parse()
+                                .ok()
+                                .filter(|d: &i32| (1..=31).contains(&d.abs()))
+                                .ok_or_else(|| format!("invalid BYMONTHDAY {d:?}"))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                "WKST" if value.eq_ignore_ascii_case("MO") => {}
+                _ => return Err(format!("unsupported RRULE part {key:?}")),
+            }
+        }
+        rule.freq = freq.ok_or("FREQ is required")?;
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err("COUNT and UNTIL can't both be given".to_owned());
+        }
+        if !rule.by_month_day.is_empty() && rule.freq != Freq::Monthly {
+            return Err("BYMONTHDAY is only supported with FREQ=MONTHLY".to_owned());
+        }
+        if !rule.by_day.is_empty() && rule.freq == Freq::Yearly {
+            return Err("BYDAY is not supported with FREQ=YEARLY".to_owned());
+        }
+        if rule.freq != Freq::Monthly && rule.by_day.iter().any(|(n, _)| n.is_some()) {
+            return Err("BYDAY ordinals like 2TU need FREQ=MONTHLY".to_owned());
+        }
+        Ok(rule)
+    }
+}
+
+fn parse_until(value: &str) -> Result<Until, String> {
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) => (
+            date,
+            NaiveTime::parse_from_str(time, "%H%M%S").map_err(|_| "invalid UNTIL time")?,
+        ),
+        // A date-only UNTIL includes that whole day.
+        None => (
+            value,
+            NaiveTime::from_hms_opt(23, 59, 59).unwrap_or_default(),
+        ),
+    };
+    let date = NaiveDate::parse_from_str(date, "%Y%m%d").map_err(|_| "invalid UNTIL date")?;
+    let until = date.and_time(time);
+    Ok(if utc {
+        Until::Utc(Utc.from_utc_datetime(&until))
+    } else {
+        Until::Local(until)
+    })
+}
+
+fn parse_by_day(value: &str) -> Result<(Option<i32>, Weekday), String> {
+    let invalid = || format!("invalid BYDAY {value:?}");
+    let split = value.len().checked_sub(2).ok_or_else(invalid)?;
+    let (ordinal, day) = value.split_at_checked(split).ok_or_else(invalid)?;
+    let day = WEEKDAYS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(day))
+        .ok_or_else(invalid)?
+        .1;
+    let ordinal = match ordinal {
+        "" => None,
+        n => Some(
+            n.parse::<i32>()
+                .ok()
+                .filter(|n| (1..=5).contains(&n.abs()))
+                .ok_or_else(invalid)?,
+        ),
+    };
+    Ok((ordinal, day))
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let freq = match self.freq {
+            Freq::Daily => "DAILY",
+            Freq::Weekly => "WEEKLY",
+            Freq::Monthly => "MONTHLY",
+            Freq::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={freq}")?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<String> = self
+                .by_day
+                .iter()
+                .map(|(n, day)| {
+                    let name = WEEKDAYS.iter().find(|(_, d)| d == day).map_or("", |w| w.0);
+                    match n {
+                        Some(n) => format!("{n}{name}"),
+                        None => name.to_owned(),
+                    }
+                })
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if !self.by_month_day.is_empty() {
+            let days: Vec<String> = self.by_month_day.iter().map(i32::to_string).collect();
+            write!(f, ";BYMONTHDAY={}", days.join(","))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        match self.until {
+            Some(Until::Utc(until)) => write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?,
+            Some(Until::Local(until)) => write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%S"))?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> Self {
+        rule.to_string()
+    }
+}
+
+impl Rule {
+    /// Candidate dates in the `n`th period after the one containing `start`,
+    /// sorted.
+    fn period_dates(&self, start: NaiveDate, n: u32) -> Vec<NaiveDate> {
+        let step = n.saturating_mul(self.interval);
+        let mut dates = match self.freq {
+            Freq::Daily => start
+                .checked_add_days(Days::new(step.into()))
+                .filter(|d| {
+                    self.by_day.is_empty() || self.by_day.iter().any(|(_, w)| *w == d.weekday())
+                })
+                .into_iter()
+                .collect(),
+            Freq::Weekly => {
+                let monday = start.week(Weekday::Mon).first_day();
+                let Some(monday) = monday.checked_add_days(Days::new(u64::from(step) * 7)) else {
+                    return Vec::new();
+                };
+                if self.by_day.is_empty() {
+                    vec![monday + Days::new(start.weekday().num_days_from_monday().into())]
+                } else {
+                    self.by_day
+                        .iter()
+                        .map(|(_, w)| monday + Days::new(w.num_days_from_monday().into()))
+                        .collect()
+                }
+            }
+            Freq::Monthly => {
+                let Some(first) = start
+                    .with_day(1)
+                    .and_then(|d| d.checked_add_months(Months::new(step)))
+                else {
+                    return Vec::new();
+                };
+                self.month_dates(first, start.day())
+            }
+            Freq::Yearly => start
+                .with_day(1)
+                .and_then(|d| d.checked_add_months(Months::new(step.saturating_mul(12))))
+                .and_then(|d| d.with_day(start.day()))
+                .into_iter()
+                .collect(),
+        };
+        dates.sort();
+        dates.dedup();
+        dates
+    }
+
+    /// Matching dates in the month starting at `first`.
+    fn month_dates(&self, first: NaiveDate, start_day: u32) -> Vec<NaiveDate> {
+        let len = first
+            .checked_add_months(Months::new(1))
+            .map_or(31, |next| (next - first).num_days() as i32);
+        let days: Vec<NaiveDate> = (0..len).map(|i| first + Days::new(i as u64)).collect();
+        let by_month_day = |d: &NaiveDate| {
+            let day = d.day() as i32;
+            self.by_month_day
+                .iter()
+                .any(|&m| m == day || m == day - len - 1)
+        };
+        let by_day = |d: &NaiveDate| {
+            self.by_day.iter().any(|&(n, w)| {
+                d.weekday() == w
+                    && n.is_none_or(|n| {
+                        let nth = (d.day() as i32 - 1) / 7 + 1;
+                        let nth_from_end = -((len - d.day() as i32) / 7 + 1);
+                        n == nth || n == nth_from_end
+                    })
+            })
+        };
+        days.into_iter()
+            .filter(
+                |d| match (self.by_month_day.is_empty(), self.by_day.is_empty()) {
+                    (true, true) => d.day() == start_day,
+                    (false, true) => by_month_day(d),
+                    (true, false) => by_day(d),
+                    (false, false) => by_month_day(d) && by_day(d),
+                },
+            )
+            .collect()
+    }
+}
+
+/// The instant `local` names on the wall clock of `tz`. Ambiguous times
+/// (when clocks go back) take the first one; times skipped when clocks go
+/// forward are moved forward by the length of the gap, as RFC 5545 asks.
+fn resolve_local(tz: Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    if let Some(at) = tz.from_local_datetime(&local).earliest() {
+        return Some(at.with_timezone(&Utc));
+    }
+    // In a gap: read the time with the offset in force before it.
+    let before = tz
+        .offset_from_local_datetime(&local.checked_sub_days(Days::new(1))?)
+        .earliest()?;
+    Some(Utc.from_utc_datetime(&(local - before.fix())))
+}
+
+impl Recurrence {
+    /// Every occurrence of the series in order, starting with `start`.
+    pub fn occurrences(&self) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        let start = self.start;
+        let tz = self.time_zone;
+        let local_start = start.with_timezone(&tz).naive_local();
+        let time = local_start.time();
+        let first = std::iter::once(start);
+        let rest = (0..)
+            .scan(0, move |empty, n| {
+                let dates = self.rule.period_dates(local_start.date(), n);
+                *empty = if dates.is_empty() { *empty + 1 } else { 0 };
+                (*empty < MAX_EMPTY_PERIODS).then_some(dates)
+            })
+            .flatten()
+            .filter_map(move |d| resolve_local(tz, d.and_time(time)))
+            .filter(move |&at| at > start);
+        first
+            .chain(rest)
+            .take(self.rule.count.map_or(usize::MAX, |c| c as usize))
+            .take_while(move |&at| match self.rule.until {
+                None => true,
+                Some(Until::Utc(until)) => at <= until,
+                Some(Until::Local(until)) => at.with_timezone(&tz).naive_local() <= until,
+            })
+    }
+
+    /// The first occurrence strictly after `after`, if the series goes on.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.occurrences().find(|&at| at > after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(rule: &str, start: &str, time_zone: Tz) -> Recurrence {
+        Recurrence {
+            rule: rule.parse().unwrap(),
+            start: start.parse().unwrap(),
+            time_zone,
+        }
+    }
+
+    fn take(recurrence: &Recurrence, n: usize) -> Vec<String> {
+        recurrence
+            .occurrences()
+            .take(n)
+            .map(|at| at.format("%Y-%m-%dT%H:%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn count_includes_the_start() {
+        let r = series("FREQ=DAILY;COUNT=3", "2025-01-30T09:00:00Z", Tz::UTC);
+        assert_eq!(
+            take(&r, 10),
+            ["2025-01-30T09:00", "2025-01-31T09:00", "2025-02-01T09:00"]
+        );
+    }
+
+    #[test]
+    fn until_is_inclusive() {
+        let r = series(
+            "FREQ=WEEKLY;UNTIL=20250115T090000Z",
+            "2025-01-01T09:00:00Z",
+            Tz::UTC,
+        );
+        assert_eq!(
+            take(&r, 10),
+            ["2025-01-01T09:00", "2025-01-08T09:00", "2025-01-15T09:00"]
+        );
+    }
+
+    #[test]
+    fn date_only_until_covers_that_day_in_the_series_zone() {
+        // 23:30 in Berlin is already the next day in UTC.
+        let r = series(
+            "FREQ=DAILY;UNTIL=20250103",
+            "2025-01-01T22:30:00Z",
+            Tz::Europe__Berlin,
+        );
+        assert_eq!(
+            take(&r, 10),
+            ["2025-01-01T22:30", "2025-01-02T22:30", "2025-01-03T22:30"]
+        );
+        assert_eq!(r.rule.to_string(), "FREQ=DAILY;UNTIL=20250103T235959");
+    }
+
+    #[test]
+    fn negative_by_day_counts_from_the_end_of_the_month() {
+        let r = series("FREQ=MONTHLY;BYDAY=-1FR", "2025-01-31T12:00:00Z", Tz::UTC);
+        assert_eq!(
+            take(&r, 4),
+            [
+                "2025-01-31T12:00",
+                "2025-02-28T12:00",
+                "2025-03-28T12:00",
+                "2025-04-25T12:00"
+            ]
+        );
+        let r = series("FREQ=MONTHLY;BYDAY=-2MO", "2025-02-17T12:00:00Z", Tz::UTC);
+        assert_eq!(
+            take(&r, 3),
+            ["2025-02-17T12:00", "2025-03-24T12:00", "2025-04-21T12:00"]
+        );
+    }
+
+    #[test]
+    fn month_day_31_skips_short_months() {
+        let r = series(
+            "FREQ=MONTHLY;BYMONTHDAY=31",
+            "2025-01-31T08:00:00Z",
+            Tz::UTC,
+        );
+        assert_eq!(
+            take(&r, 4),
+            [
+                "2025-01-31T08:00",
+                "2025-03-31T08:00",
+                "2025-05-31T08:00",
+                "2025-07-31T08:00"
+            ]
+        );
+        let r = series(
+            "FREQ=MONTHLY;BYMONTHDAY=-1",
+            "2025-01-31T08:00:00Z",
+            Tz::UTC,
+        );
+        assert_eq!(
+            take(&r, 3),
+            ["2025-01-31T08:00", "2025-02-28T08:00", "2025-03-31T08:00"]
+        );
+    }
+
+    #[test]
+    fn keeps_wall_clock_time_across_dst() {
+        // 09:00 in Berlin is 08:00 UTC in winter and 07:00 UTC in summer.
+        let r = series("FREQ=DAILY", "2025-03-29T08:00:00Z", Tz::Europe__Berlin);
+        assert_eq!(
+            take(&r, 3),
+            ["2025-03-29T08:00", "2025-03-30T07:00", "2025-03-31T07:00"]
+        );
+        let r = series("FREQ=DAILY", "2025-03-29T08:00:00Z", Tz::UTC);
+        assert_eq!(take(&r, 2)[1], "2025-03-30T08:00");
+    }
+
+    #[test]
+    fn times_skipped_by_dst_move_forward() {
+        // 02:30 doesn't exist in Berlin on 2025-03-30; it becomes 03:30 CEST.
+        let r = series("FREQ=DAILY", "2025-03-29T01:30:00Z", Tz::Europe__Berlin);
+        assert_eq!(
+            take(&r, 3),
+            ["2025-03-29T01:30", "2025-03-30T01:30", "2025-03-31T00:30"]
+        );
+    }
+
+    #[test]
+    fn weekdays_are_taken_in_the_series_zone() {
+        // Monday 00:30 in Tokyo is still Sunday in UTC.
+        let r = series(
+            "FREQ=WEEKLY;BYDAY=MO",
+            "2025-01-05T15:30:00Z",
+            Tz::Asia__Tokyo,
+        );
+        assert_eq!(take(&r, 2), ["2025-01-05T15:30", "2025-01-12T15:30"]);
+    }
+
+    #[test]
+    fn series_saved_without_a_zone_run_in_utc() {
+        let r: Recurrence =
+            serde_json::from_str(r#"{"rule":"FREQ=DAILY","start":"2025-01-01T09:00:00Z"}"#)
+                .unwrap();
+        assert_eq!(r.time_zone, Tz::UTC);
+        let json = serde_json::to_value(series(
+            "FREQ=DAILY",
+            "2025-01-01T09:00:00Z",
+            Tz::Europe__Berlin,
+        ))
+        .unwrap();
+        assert_eq!(json["time_zone"], "Europe/Berlin");
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for rule in [
+            "",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;COUNT=0",
+            "FREQ=DAILY;COUNT=2;UNTIL=20250101",
+            "FREQ=WEEKLY;BYMONTHDAY=1",
+            "FREQ=WEEKLY;BYDAY=2TU",
+            "FREQ=MONTHLY;BYDAY=6MO",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+        ] {
+            assert!(rule.parse::<Rule>().is_err(), "{rule}");
+        }
+    }
+
+    #[test]
+    fn rejects_parts_the_engine_does_not_evaluate() {
+        for (rule, part) in [
+            ("FREQ=MONTHLY;BYDAY=MO,TU;BYSETPOS=-1", "BYSETPOS"),
+            ("FREQ=WEEKLY;WKST=SU", "WKST"),
+            ("FREQ=DAILY;BYHOUR=9", "BYHOUR"),
+            ("FREQ=DAILY;BYMINUTE=30", "BYMINUTE"),
+            ("FREQ=DAILY;BYSECOND=0", "BYSECOND"),
+            ("FREQ=YEARLY;BYMONTH=3", "BYMONTH"),
+            ("FREQ=YEARLY;BYWEEKNO=20", "BYWEEKNO"),
+            ("FREQ=YEARLY;BYYEARDAY=100", "BYYEARDAY"),
+            ("FREQ=DAILY;DTSTART=20250101T090000Z", "DTSTART"),
+        ] {
+            let err = rule.parse::<Rule>().unwrap_err();
+            assert!(err.contains(part), "{rule}: {err}");
+        }
+        for freq in ["SECONDLY", "MINUTELY", "HOURLY"] {
+            let err = format!("FREQ={freq}").parse::<Rule>().unwrap_err();
+            assert_eq!(err, format!("unsupported FREQ {freq:?}"));
+        }
+        assert!("FREQ=YEARLY;BYDAY=MO".parse::<Rule>().is_err());
+        assert!("FREQ=WEEKLY;WKST=MO".parse::<Rule>().is_ok());
+    }
+
+    #[test]
+    fn rejects_repeated_parts() {
+        let err = "FREQ=DAILY;INTERVAL=2;freq=WEEKLY"
+            .parse::<Rule>()
+            .unwrap_err();
+        assert_eq!(err, "FREQ given more than once");
+    }
+}
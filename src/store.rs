@@ -0,0 +1,497 @@
+mod journal;
+mod snapshot;
+mod sqlite;
+
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use std::{fmt, io};
+
+use crate::list::TodoList;
+use crate::tag::Tag;
+use crate::todo::Todo;
+
+pub use journal::JournalStore;
+pub use snapshot::SnapshotStore;
+pub use sqlite::SqliteStore;
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// The backend failed to read or write.
+    Backend(String),
+    /// A write would give two records the same [`Record::unique_key`].
+    Conflict {
+        collection: &'static str,
+        field: &'static str,
+    },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Backend(e) => write!(f, "store error: {e}"),
+            StoreError::Conflict { collection, field } => {
+                write!(f, "store error: duplicate {field} in {collection}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Backend(format!("sqlite: {e}"))
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Backend(e.to_string())
+    }
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// Something kept in a [`Collection`].
+pub trait Record: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Collection name, used as the SQLite table and in journal entries.
+    const COLLECTION: &'static str;
+
+    /// Field that must be unique within the collection, if any.
+    const UNIQUE_FIELD: Option<&'static str> = None;
+
+    fn id(&self) -> &str;
+
+    /// The value of [`Record::UNIQUE_FIELD`], normalized the way it is
+    /// compared.
+    fn unique_key(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Fails with [`StoreError::Conflict`] if a record in `records` other than
+/// `record` itself has the same unique key.
+fn check_unique<'a, T: Record>(
+    records: impl IntoIterator<Item = &'a T>,
+    record: &T,
+) -> StoreResult<()> {
+    let (Some(field), Some(key)) = (T::UNIQUE_FIELD, record.unique_key()) else {
+        return Ok(());
+    };
+    let taken = records
+        .into_iter()
+        .any(|r| r.id() != record.id() && r.unique_key().as_ref() == Some(&key));
+    if taken {
+        return Err(StoreError::Conflict {
+            collection: T::COLLECTION,
+            field,
+        });
+    }
+    Ok(())
+}
+
+impl Record for Todo {
+    const COLLECTION: &'static str = "todos";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Record for Tag {
+    const COLLECTION: &'static str = "tags";
+    const UNIQUE_FIELD: Option<&'static str> = Some("name");
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Tag names are unique ignoring case.
+    fn unique_key(&self) -> Option<String> {
+        Some(self.name.to_lowercase())
+    }
+}
+
+impl Record for TodoList {
+    const COLLECTION: &'static str = "lists";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// CRUD access to one kind of record.
+pub trait Collection<T>: Send + Sync {
+    fn get(&self, id: &str) -> StoreResult<Option<T>>;
+
+    fn list(&self) -> StoreResult<Vec<T>>;
+
+    fn insert(&self, record: T) -> StoreResult<()>;
+
+    /// Applies `f` to the record with the given id and returns the updated
+    /// record, or `None` if no such record exists.
+    fn update(&self, id: &str, f: &mut dyn FnMut(&mut T)) -> StoreResult<Option<T>> {
+        self.update_if(id, &mut |record| {
+            f(record);
+            true
+        })
+    }
+
+    /// Like [`Collection::update`], but nothing is written if `f` returns
+    /// `false`; the record is then returned as it was.
+    fn update_if(&self, id: &str, f: &mut dyn FnMut(&mut T) -> bool) -> StoreResult<Option<T>>;
+
+    /// Returns whether a record was removed.
+    fn delete(&self, id: &str) -> StoreResult<bool>;
+}
+
+/// Storage backend for the app. Resolvers only talk to this trait, so the
+/// backing storage can be swapped without touching the schema.
+pub trait TodoStore: Send + Sync {
+    fn todos(&self) -> &dyn Collection<Todo>;
+
+    fn tags(&self) -> &dyn Collection<Tag>;
+
+    fn lists(&self) -> &dyn Collection<TodoList>;
+
+    /// Persists any buffered changes. Called periodically and on shutdown.
+    fn flush(&self) -> StoreResult<()> {
+        Ok(())
+    }
+}
+
+/// Spawns a task that calls [`TodoStore::flush`] every `period`. The task
+/// ends once the store is dropped.
+pub fn flush_every(store: &Arc<dyn TodoStore>, period: Duration) {
+    let store: Weak<dyn TodoStore> = Arc::downgrade(store);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let Some(store) = store.upgrade() else {
+                break;
+            };
+            if let Err(e) = store.flush() {
+                eprintln!("failed to flush store: {e}");
+            }
+        }
+    });
+}
+
+/// A mutation about to be applied to an in-memory collection.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Change {
+    Created {
+        #[serde(alias = "todo")]
+        record: Value,
+    },
+    Updated {
+        #[serde(alias = "todo")]
+        record: Value,
+    },
+    Deleted {
+        id: String,
+    },
+}
+
+/// Called with the collection name and the change before a mutation is
+/// applied, while the collection is locked. An error aborts the mutation.
+type Hook = Arc<dyn Fn(&'static str, Change) -> StoreResult<()> + Send + Sync>;
+
+struct MemoryCollection<T> {
+    items: Mutex<Vec<T>>,
+    hook: Option<Hook>,
+}
+
+impl<T: Record> MemoryCollection<T> {
+    fn new(items: Vec<T>, hook: Option<Hook>) -> Self {
+        Self {
+            items: Mutex::new(items),
+            hook,
+        }
+    }
+
+    fn notify(&self, change: impl FnOnce() -> StoreResult<Change>) -> StoreResult<()> {
+        match &self.hook {
+            Some(hook) => hook(T::COLLECTION, change()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies a recorded change without running the hook; used for replay.
+    /// Created and updated records are upserted, so replaying is idempotent.
+    fn apply(&self, change: Change) -> StoreResult<()> {
+        let mut items = self.items.lock();
+        match change {
+            Change::Created { record } | Change::Updated { record } => {
+                let record: T = serde_json::from_value(record)?;
+                match items.iter_mut().find(|r| r.id() == record.id()) {
+                    Some(existing) => *existing = record,
+                    None => items.push(record),
+                }
+            }
+            Change::Deleted { id } => items.retain(|r| r.id() != id),
+        }
+        Ok(())
+    }
+}
+
+impl<T: Record> Collection<T> for MemoryCollection<T> {
+    fn get(&self, id: &str) -> StoreResult<Option<T>> {
+        Ok(self.items.lock().iter().find(|r| r.id() == id).cloned())
+    }
+
+    fn list(&self) -> StoreResult<Vec<T>> {
+        Ok(self.items.lock().clone())
+    }
+
+    fn insert(&self, record: T) -> StoreResult<()> {
+        let mut items = self.items.lock();
+        check_unique(items.iter(), &record)?;
+        self.notify(|| {
+            Ok(Change::Created {
+                record: serde_json::to_value(&record)?,
+            })
+        })?;
+        items.push(record);
+        Ok(())
+    }
+
+    fn update_if(&self, id: &str, f: &mut dyn FnMut(&mut T) -> bool) -> StoreResult<Option<T>> {
+        let mut items = self.items.lock();
+        let Some(index) = items.iter().position(|r| r.id() == id) else {
+            return Ok(None);
+        };
+        let mut updated = items[index].clone();
+        if !f(&mut updated) {
+            return Ok(Some(items[index].clone()));
+        }
+        check_unique(items.iter(), &updated)?;
+        self.notify(|| {
+            Ok(Change::Updated {
+                record: serde_json::to_value(&updated)?,
+            })
+        })?;
+        items[index] = updated.clone();
+        Ok(Some(updated))
+    }
+
+    fn delete(&self, id: &str) -> StoreResult<bool> {
+        let mut items = self.items.lock();
+        let Some(index) = items.iter().position(|r| r.id() == id) else {
+            return Ok(false);
+        };
+        self.notify(|| Ok(Change::Deleted { id: id.to_owned() }))?;
+        items.remove(index);
+        Ok(true)
+    }
+}
+
+/// Keeps everything in `Vec`s behind mutexes; everything is lost on restart.
+pub struct MemoryStore {
+    todos: MemoryCollection<Todo>,
+    tags: MemoryCollection<Tag>,
+    lists: MemoryCollection<TodoList>,
+}
+
+impl MemoryStore {
+    pub fn new(todos: Vec<Todo>) -> Self {
+        Self::with_hook(todos, Vec::new(), Vec::new(), None)
+    }
+
+    fn with_hook(
+        todos: Vec<Todo>,
+        tags: Vec<Tag>,
+        lists: Vec<TodoList>,
+        hook: Option<Hook>,
+    ) -> Self {
+        Self {
+            todos: MemoryCollection::new(todos, hook.clone()),
+            tags: MemoryCollection::new(tags, hook.clone()),
+            lists: MemoryCollection::new(lists, hook),
+        }
+    }
+
+    /// Routes a recorded change to the collection it belongs to.
+    fn apply(&self, collection: &str, change: Change) -> StoreResult<()> {
+        if collection == Todo::COLLECTION {
+            self.todos.apply(change)
+        } else if collection == Tag::COLLECTION {
+            self.tags.apply(change)
+        } else if collection == TodoList::COLLECTION {
+            self.lists.apply(change)
+        } else {
+            Err(StoreError::Backend(format!(
+                "unknown collection {collection:?}"
+            )))
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl TodoStore for MemoryStore {
+    fn todos(&self) -> &dyn Collection<Todo> {
+        &self.todos
+    }
+
+    fn tags(&self) -> &dyn Collection<Tag> {
+        &self.tags
+    }
+
+    fn lists(&self) -> &dyn Collection<TodoList> {
+        &self.lists
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, empty directory under the system temp dir.
+    pub(super) fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("todos-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn memory_store_round_trips_records() {
+        let store = MemoryStore::default();
+        let first = Todo::new("first".into());
+        let second = Todo::new("second".into());
+        store.todos().insert(first.clone()).unwrap();
+        store.todos().insert(second.clone()).unwrap();
+
+        let updated = store
+            .todos()
+            .update(&first.id, &mut |t| t.completed = true)
+            .unwrap();
+        assert!(updated.is_some_and(|t| t.completed));
+        assert!(store.todos().get(&first.id).unwrap().unwrap().completed);
+        assert!(
+            store
+                .todos()
+                .update("missing", &mut |_| {})
+                .unwrap()
+                .is_none()
+        );
+
+        assert!(store.todos().delete(&second.id).unwrap());
+        assert!(!store.todos().delete(&second.id).unwrap());
+        let ids: Vec<String> = store
+            .todos()
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, [first.id]);
+    }
+
+    #[test]
+    fn update_if_writes_nothing_when_declined() {
+        let calls = Arc::new(Mutex::new(0));
+        let hook: Hook = {
+            let calls = calls.clone();
+            Arc::new(move |_, _| {
+                *calls.lock() += 1;
+                Ok(())
+            })
+        };
+        let todo = Todo::new("keep".into());
+        let store = MemoryStore::with_hook(vec![todo.clone()], Vec::new(), Vec::new(), Some(hook));
+        let returned = store
+            .todos()
+            .update_if(&todo.id, &mut |t| {
+                t.title = "changed".into();
+                false
+            })
+            .unwrap();
+        assert_eq!(returned.unwrap().title, "keep");
+        assert_eq!(store.todos().get(&todo.id).unwrap().unwrap().title, "keep");
+        assert_eq!(*calls.lock(), 0);
+    }
+
+    #[test]
+    fn failing_hook_aborts_the_write() {
+        let hook: Hook = Arc::new(|_, _| Err(StoreError::Backend("disk full".into())));
+        let store = MemoryStore::with_hook(Vec::new(), Vec::new(), Vec::new(), Some(hook));
+        assert!(store.todos().insert(Todo::new("lost".into())).is_err());
+        assert!(store.todos().list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn replayed_changes_are_upserted() {
+        let store = MemoryStore::default();
+        let mut todo = Todo::new("v1".into());
+        let created = Change::Created {
+            record: serde_json::to_value(&todo).unwrap(),
+        };
+        store.apply(Todo::COLLECTION, created).unwrap();
+        todo.title = "v2".into();
+        for _ in 0..2 {
+            let updated = Change::Updated {
+                record: serde_json::to_value(&todo).unwrap(),
+            };
+            store.apply(Todo::COLLECTION, updated).unwrap();
+        }
+        let todos = store.todos().list().unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].title, "v2");
+
+        let deleted = Change::Deleted { id: todo.id };
+        store.apply(Todo::COLLECTION, deleted).unwrap();
+        assert!(store.todos().list().unwrap().is_empty());
+        assert!(
+            store
+                .apply("widgets", Change::Deleted { id: "x".into() })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn memory_store_rejects_duplicate_tag_names_ignoring_case() {
+        let store = MemoryStore::default();
+        let work = Tag::new("Work".into(), None);
+        let home = Tag::new("home".into(), None);
+        store.tags().insert(work.clone()).unwrap();
+        store.tags().insert(home.clone()).unwrap();
+
+        let err = store.tags().insert(Tag::new("wORK".into(), None));
+        assert!(matches!(
+            err,
+            Err(StoreError::Conflict { field: "name", .. })
+        ));
+        let err = store
+            .tags()
+            .update(&home.id, &mut |t| t.name = "WORK".into());
+        assert!(matches!(
+            err,
+            Err(StoreError::Conflict { field: "name", .. })
+        ));
+        assert_eq!(store.tags().list().unwrap().len(), 2);
+        assert_eq!(store.tags().get(&home.id).unwrap().unwrap().name, "home");
+
+        let renamed = store
+            .tags()
+            .update(&work.id, &mut |t| t.name = "work".into());
+        assert_eq!(renamed.unwrap().unwrap().name, "work");
+    }
+}
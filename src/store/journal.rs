@@ -0,0 +1,337 @@
+//! Write-ahead journal: every mutation is appended to a JSON-lines log before
+//! it is applied in memory. On startup the store is rebuilt from the last
+//! snapshot plus the log; [`TodoStore::flush`] compacts the log into a fresh
+//! snapshot and archives the compacted log segment.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::snapshot::Snapshot;
+use super::{Change, Collection, Hook, MemoryStore, StoreError, StoreResult, TodoStore};
+use crate::list::TodoList;
+use crate::tag::Tag;
+use crate::todo::Todo;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    seq: u64,
+    /// Milliseconds since the Unix epoch.
+    at: u64,
+    /// Entries written before tags existed have no collection and are todos.
+    #[serde(default = "default_collection")]
+    collection: String,
+    #[serde(flatten)]
+    change: Change,
+}
+
+fn default_collection() -> String {
+    "todos".to_owned()
+}
+
+struct Log {
+    file: File,
+    /// Sequence number of the last appended entry.
+    seq: u64,
+    /// Sequence number of the last entry folded into the snapshot.
+    compacted: u64,
+}
+
+pub struct JournalStore {
+    inner: MemoryStore,
+    journal_path: PathBuf,
+    snapshot_path: PathBuf,
+    log: Arc<Mutex<Log>>,
+}
+
+impl JournalStore {
+    pub fn open(
+        journal_path: impl Into<PathBuf>,
+        snapshot_path: impl Into<PathBuf>,
+    ) -> StoreResult<Self> {
+        let journal_path = journal_path.into();
+        let snapshot_path = snapshot_path.into();
+
+        let snapshot = Snapshot::load(&snapshot_path)?;
+        let compacted = snapshot.seq;
+        let replayed = snapshot.into_store(None);
+        let seq = replay(&journal_path, &replayed)?.unwrap_or(compacted);
+        let log = Arc::new(Mutex::new(Log {
+            file: open_append(&journal_path)?,
+            seq,
+            compacted,
+        }));
+
+        // Collections call the hook while locked, so entries are appended in
+        // the same order the changes are applied.
+        let hook: Hook = {
+            let log = log.clone();
+            Arc::new(move |collection, change| log.lock().append(collection, change))
+        };
+        Ok(Self {
+            inner: replayed.capture(|state| state).into_store(Some(hook)),
+            journal_path,
+            snapshot_path,
+            log,
+        })
+    }
+}
+
+fn open_append(path: &Path) -> StoreResult<File> {
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+/// Applies every entry in the journal to `store` and returns the last
+/// sequence number, if there were any entries. Entries carry the full
+/// resulting record, so replaying entries that were already compacted into
+/// the snapshot is harmless.
+fn replay(path: &Path, store: &MemoryStore) -> StoreResult<Option<u64>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut seq = None;
+    let mut valid_len = 0;
+    let mut lines = BufReader::new(file).lines().peekable();
+    while let Some(line) = lines.next() {
+        let line = line?;
+        let entry: Entry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            // A torn final line means we crashed mid-append; that mutation
+            // was never acknowledged, so cut it off before appending again.
+            Err(e) if lines.peek().is_none() => {
+                eprintln!(
+                    "dropping truncated journal entry in {}: {e}",
+                    path.display()
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)?
+                    .set_len(valid_len)?;
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        valid_len += line.len() as u64 + 1;
+        seq = Some(entry.seq);
+        store.apply(&entry.collection, entry.change)?;
+    }
+    Ok(seq)
+}
+
+impl Log {
+    fn append(&mut self, collection: &str, change: Change) -> StoreResult<()> {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| StoreError::Backend(e.to_string()))?
+            .as_millis() as u64;
+        let entry = Entry {
+            seq: self.seq + 1,
+            at,
+            collection: collection.to_owned(),
+            change,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.seq = entry.seq;
+        Ok(())
+    }
+}
+
+impl TodoStore for JournalStore {
+    fn todos(&self) -> &dyn Collection<Todo> {
+        self.inner.todos()
+    }
+
+    fn tags(&self) -> &dyn Collection<Tag> {
+        self.inner.tags()
+    }
+
+    fn lists(&self) -> &dyn Collection<TodoList> {
+        self.inner.lists()
+    }
+
+    /// Compacts the journal: writes a snapshot of the current state, moves the
+    /// journal aside as `<journal>.<seq>` to keep the history, and starts a
+    /// new empty journal.
+    fn flush(&self) -> StoreResult<()> {
+        // Writers take a collection lock and then the log lock, so take them
+        // in the same order; holding both keeps the snapshot and `seq` in step.
+        self.inner.capture(|mut snapshot| {
+            let mut log = self.log.lock();
+            if log.seq == log.compacted {
+                return Ok(());
+            }
+            snapshot.seq = log.seq;
+            snapshot.save(&self.snapshot_path)?;
+
+            let mut archive = self.journal_path.as_os_str().to_owned();
+            archive.push(format!(".{}", log.seq));
+            fs::rename(&self.journal_path, archive)?;
+            log.file = open_append(&self.journal_path)?;
+            log.compacted = log.seq;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::temp_dir;
+
+    struct Paths {
+        dir: PathBuf,
+        journal: PathBuf,
+        snapshot: PathBuf,
+    }
+
+    impl Paths {
+        fn new() -> Self {
+            let dir = temp_dir();
+            Paths {
+                journal: dir.join("todos.jsonl"),
+                snapshot: dir.join("todos.json"),
+                dir,
+            }
+        }
+
+        fn open(&self) -> JournalStore {
+            JournalStore::open(&self.journal, &self.snapshot).unwrap()
+        }
+
+        fn journal_lines(&self) -> usize {
+            fs::read_to_string(&self.journal).unwrap().lines().count()
+        }
+    }
+
+    impl Drop for Paths {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn replays_the_journal_on_open() {
+        let paths = Paths::new();
+        let kept = Todo::new("kept".into());
+        let removed = Todo::new("removed".into());
+        {
+            let store = paths.open();
+            store.todos().insert(kept.clone()).unwrap();
+            store.todos().insert(removed.clone()).unwrap();
+            store
+                .todos()
+                .update(&kept.id, &mut |t| t.completed = true)
+                .unwrap();
+            store.todos().delete(&removed.id).unwrap();
+            store.tags().insert(Tag::new("work".into(), None)).unwrap();
+        }
+        assert_eq!(paths.journal_lines(), 5);
+        assert!(!paths.snapshot.exists());
+
+        let store = paths.open();
+        let todos = store.todos().list().unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].id, kept.id);
+        assert!(todos[0].completed);
+        assert_eq!(store.tags().list().unwrap()[0].name, "work");
+    }
+
+    #[test]
+    fn compaction_snapshots_and_archives_the_journal() {
+        let paths = Paths::new();
+        let first = Todo::new("first".into());
+        let second = Todo::new("second".into());
+        {
+            let store = paths.open();
+            store.todos().insert(first.clone()).unwrap();
+            store
+                .todos()
+                .update(&first.id, &mut |t| t.title = "renamed".into())
+                .unwrap();
+            store.flush().unwrap();
+            assert_eq!(paths.journal_lines(), 0);
+            assert!(paths.dir.join("todos.jsonl.2").exists());
+            assert_eq!(Snapshot::load(&paths.snapshot).unwrap().seq, 2);
+
+            // Nothing new to compact.
+            store.flush().unwrap();
+            assert!(!paths.dir.join("todos.jsonl.0").exists());
+
+            store.todos().insert(second.clone()).unwrap();
+        }
+
+        let store = paths.open();
+        let titles: Vec<String> = store
+            .todos()
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.title)
+            .collect();
+        assert_eq!(titles, ["renamed", "second"]);
+
+        // Sequence numbers carry on after the snapshot.
+        store.todos().delete(&first.id).unwrap();
+        let last = fs::read_to_string(&paths.journal).unwrap();
+        let seqs: Vec<u64> = last
+            .lines()
+            .map(|l| serde_json::from_str::<Entry>(l).unwrap().seq)
+            .collect();
+        assert_eq!(seqs, [3, 4]);
+    }
+
+    #[test]
+    fn drops_a_torn_final_entry() {
+        let paths = Paths::new();
+        let todo = Todo::new("kept".into());
+        {
+            let store = paths.open();
+            store.todos().insert(todo.clone()).unwrap();
+        }
+        let mut file = open_append(&paths.journal).unwrap();
+        file.write_all(br#"{"seq":2,"at":0,"collection":"todos","type":"del"#)
+            .unwrap();
+        drop(file);
+
+        let store = paths.open();
+        assert_eq!(store.todos().list().unwrap().len(), 1);
+        assert_eq!(paths.journal_lines(), 1);
+        store.todos().delete(&todo.id).unwrap();
+        drop(store);
+        assert!(paths.open().todos().list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejected_writes_are_not_journaled() {
+        let paths = Paths::new();
+        let store = paths.open();
+        store.tags().insert(Tag::new("work".into(), None)).unwrap();
+        assert!(store.tags().insert(Tag::new("Work".into(), None)).is_err());
+        assert_eq!(paths.journal_lines(), 1);
+    }
+
+    #[test]
+    fn reads_entries_written_before_collections() {
+        let paths = Paths::new();
+        let todo = Todo::new("old".into());
+        let entry = serde_json::json!({
+            "seq": 1,
+            "at": 0,
+            "type": "created",
+            "todo": todo,
+        });
+        fs::write(&paths.journal, format!("{entry}\n")).unwrap();
+        let store = paths.open();
+        assert_eq!(store.todos().get(&todo.id).unwrap().unwrap().title, "old");
+    }
+}
@@ -0,0 +1,206 @@
+//! In-memory store that is periodically written to a JSON snapshot file.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{Collection, Hook, MemoryStore, StoreResult, TodoStore};
+use crate::list::TodoList;
+use crate::tag::Tag;
+use crate::todo::Todo;
+
+/// On-disk layout of a snapshot file.
+#[derive(Default, Serialize, Deserialize)]
+pub(super) struct Snapshot {
+    /// Last journal entry folded into this snapshot; only used by the
+    /// journal backend.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub seq: u64,
+    pub todos: Vec<Todo>,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub lists: Vec<TodoList>,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+impl Snapshot {
+    /// Reads the snapshot at `path`, or an empty one if the file is missing.
+    pub fn load(path: &Path) -> StoreResult<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes to a sibling temp file and renames it over `path`, so readers
+    /// never observe a half-written snapshot.
+    pub fn save(&self, path: &Path) -> StoreResult<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut file = fs::File::create(&tmp)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn into_store(self, hook: Option<Hook>) -> MemoryStore {
+        MemoryStore::with_hook(self.todos, self.tags, self.lists, hook)
+    }
+}
+
+impl MemoryStore {
+    /// Copies every collection while holding all of their locks, so the
+    /// snapshot is a consistent cut. `f` runs before the locks are released.
+    pub(super) fn capture<R>(&self, f: impl FnOnce(Snapshot) -> R) -> R {
+        let todos = self.todos.items.lock();
+        let tags = self.tags.items.lock();
+        let lists = self.lists.items.lock();
+        f(Snapshot {
+            seq: 0,
+            todos: todos.clone(),
+            tags: tags.clone(),
+            lists: lists.clone(),
+        })
+    }
+}
+
+/// Serves reads and writes from memory and marks itself dirty on every
+/// mutation; [`TodoStore::flush`] writes the snapshot if anything changed, so
+/// flushing on an interval debounces bursts of mutations into a single write.
+pub struct SnapshotStore {
+    inner: MemoryStore,
+    path: PathBuf,
+    dirty: Arc<AtomicBool>,
+    flush_lock: Mutex<()>,
+}
+
+impl SnapshotStore {
+    pub fn open(path: impl Into<PathBuf>) -> StoreResult<Self> {
+        let path = path.into();
+        let dirty = Arc::new(AtomicBool::new(false));
+        let hook: Hook = {
+            let dirty = dirty.clone();
+            Arc::new(move |_, _| {
+                dirty.store(true, Ordering::Release);
+                Ok(())
+            })
+        };
+        Ok(Self {
+            inner: Snapshot::load(&path)?.into_store(Some(hook)),
+            path,
+            dirty,
+            flush_lock: Mutex::new(()),
+        })
+    }
+}
+
+impl TodoStore for SnapshotStore {
+    fn todos(&self) -> &dyn Collection<Todo> {
+        self.inner.todos()
+    }
+
+    fn tags(&self) -> &dyn Collection<Tag> {
+        self.inner.tags()
+    }
+
+    fn lists(&self) -> &dyn Collection<TodoList> {
+        self.inner.lists()
+    }
+
+    fn flush(&self) -> StoreResult<()> {
+        let _guard = self.flush_lock.lock();
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        // The hook marks the store dirty while holding a collection lock, so
+        // capturing after the swap always sees that mutation applied.
+        let snapshot = self.inner.capture(|snapshot| snapshot);
+        snapshot
+            .save(&self.path)
+            .inspect_err(|_| self.dirty.store(true, Ordering::Release))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::temp_dir;
+
+    #[test]
+    fn round_trips_records_across_reopen() {
+        let dir = temp_dir();
+        let path = dir.join("todos.json");
+        let todo = Todo::new("first".into());
+        let tag = Tag::new("work".into(), None);
+        {
+            let store = SnapshotStore::open(&path).unwrap();
+            store.todos().insert(todo.clone()).unwrap();
+            store.tags().insert(tag.clone()).unwrap();
+            store
+                .lists()
+                .insert(TodoList::new("Errands".into(), None))
+                .unwrap();
+            store
+                .todos()
+                .update(&todo.id, &mut |t| t.completed = true)
+                .unwrap();
+            store.flush().unwrap();
+        }
+
+        let store = SnapshotStore::open(&path).unwrap();
+        let todos = store.todos().list().unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].id, todo.id);
+        assert!(todos[0].completed);
+        assert_eq!(store.tags().list().unwrap()[0].id, tag.id);
+        assert_eq!(store.lists().list().unwrap()[0].name, "Errands");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_only_when_dirty() {
+        let dir = temp_dir();
+        let path = dir.join("todos.json");
+        let store = SnapshotStore::open(&path).unwrap();
+        store.flush().unwrap();
+        assert!(!path.exists());
+
+        store.todos().insert(Todo::new("first".into())).unwrap();
+        store.flush().unwrap();
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+        store.flush().unwrap();
+        assert!(!path.exists(), "flushed again without changes");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn loads_snapshots_from_before_tags_and_lists() {
+        let dir = temp_dir();
+        let path = dir.join("todos.json");
+        fs::write(
+            &path,
+            r#"{"todos":[{"id":"1","title":"old","completed":false}]}"#,
+        )
+        .unwrap();
+        let store = SnapshotStore::open(&path).unwrap();
+        let todo = store.todos().get("1").unwrap().unwrap();
+        assert_eq!(todo.title, "old");
+        assert_eq!(todo.list_id, crate::list::inbox_id());
+        assert!(store.tags().list().unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,295 @@
+//! SQLite backend on `rusqlite`, with SQLite itself compiled in.
+//!
+//! Each collection gets its own table holding records as JSON documents keyed
+//! by id, so new fields don't need a schema migration. Insertion order is kept
+//! through `rowid`.
+
+use parking_lot::Mutex;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::{Collection, Record, StoreError, StoreResult, TodoStore, check_unique};
+use crate::list::TodoList;
+use crate::tag::Tag;
+use crate::todo::Todo;
+
+pub struct SqliteStore {
+    todos: SqliteCollection<Todo>,
+    tags: SqliteCollection<Tag>,
+    lists: SqliteCollection<TodoList>,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database at `path` and makes sure the schema
+    /// exists.
+    pub fn open(path: &str) -> StoreResult<Self> {
+        let conn = Arc::new(Mutex::new(Connection::open(path)?));
+        Ok(Self {
+            todos: SqliteCollection::create(&conn)?,
+            tags: SqliteCollection::create(&conn)?,
+            lists: SqliteCollection::create(&conn)?,
+        })
+    }
+}
+
+impl TodoStore for SqliteStore {
+    fn todos(&self) -> &dyn Collection<Todo> {
+        &self.todos
+    }
+
+    fn tags(&self) -> &dyn Collection<Tag> {
+        &self.tags
+    }
+
+    fn lists(&self) -> &dyn Collection<TodoList> {
+        &self.lists
+    }
+}
+
+/// The table named after `T::COLLECTION`.
+struct SqliteCollection<T> {
+    conn: Arc<Mutex<Connection>>,
+    select_one: String,
+    select_all: String,
+    insert: String,
+    update: String,
+    delete: String,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<T: Record> SqliteCollection<T> {
+    fn create(conn: &Arc<Mutex<Connection>>) -> StoreResult<Self> {
+        let table = T::COLLECTION;
+        conn.lock().execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {table} (id TEXT PRIMARY KEY NOT NULL, data TEXT NOT NULL)"
+            ),
+            [],
+        )?;
+        if let Some(field) = T::UNIQUE_FIELD {
+            // Backs up `check_unique`, which also folds non-ASCII case.
+            conn.lock().execute(
+                &format!(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS {table}_{field} \
+                     ON {table} (lower(json_extract(data, '$.{field}')))"
+                ),
+                [],
+            )?;
+        }
+        Ok(Self {
+            conn: conn.clone(),
+            select_one: format!("SELECT data FROM {table} WHERE id = ?1"),
+            select_all: format!("SELECT data FROM {table} ORDER BY rowid"),
+            insert: format!("INSERT INTO {table} (id, data) VALUES (?1, ?2)"),
+            update: format!("UPDATE {table} SET data = ?2 WHERE id = ?1"),
+            delete: format!("DELETE FROM {table} WHERE id = ?1"),
+            _record: PhantomData,
+        })
+    }
+}
+
+fn decode<T: Record>(data: &str) -> StoreResult<T> {
+    Ok(serde_json::from_str(data)?)
+}
+
+fn encode<T: Record>(record: &T) -> StoreResult<String> {
+    Ok(serde_json::to_string(record)?)
+}
+
+/// The `data` column of the row with the given id.
+fn select_one(conn: &Connection, sql: &str, id: &str) -> StoreResult<Option<String>> {
+    Ok(conn
+        .prepare_cached(sql)?
+        .query_row([id], |row| row.get(0))
+        .optional()?)
+}
+
+impl<T: Record> SqliteCollection<T> {
+    fn select_all(&self, conn: &Connection) -> StoreResult<Vec<T>> {
+        let mut stmt = conn.prepare_cached(&self.select_all)?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.map(|data| decode(&data?)).collect()
+    }
+
+    /// Checks `record` against the other rows; the caller holds the
+    /// connection lock until it has written `record`.
+    fn check_unique(&self, conn: &Connection, record: &T) -> StoreResult<()> {
+        if T::UNIQUE_FIELD.is_none() {
+            return Ok(());
+        }
+        check_unique(&self.select_all(conn)?, record)
+    }
+}
+
+/// Turns a unique index violation into [`StoreError::Conflict`].
+fn constraint<T: Record>(e: rusqlite::Error) -> StoreError {
+    match (&e, T::UNIQUE_FIELD) {
+        (rusqlite::Error::SqliteFailure(err, _), Some(field))
+            if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE =>
+        {
+            StoreError::Conflict {
+                collection: T::COLLECTION,
+                field,
+            }
+        }
+        _ => e.into(),
+    }
+}
+
+impl<T: Record> Collection<T> for SqliteCollection<T> {
+    fn get(&self, id: &str) -> StoreResult<Option<T>> {
+        let conn = self.conn.lock();
+        select_one(&conn, &self.select_one, id)?
+            .map(|data| decode(&data))
+            .transpose()
+    }
+
+    fn list(&self) -> StoreResult<Vec<T>> {
+        self.select_all(&self.conn.lock())
+    }
+
+    fn insert(&self, record: T) -> StoreResult<()> {
+        let data = encode(&record)?;
+        let conn = self.conn.lock();
+        self.check_unique(&conn, &record)?;
+        conn.prepare_cached(&self.insert)?
+            .execute(params![record.id(), data])
+            .map_err(constraint::<T>)?;
+        Ok(())
+    }
+
+    fn update_if(&self, id: &str, f: &mut dyn FnMut(&mut T) -> bool) -> StoreResult<Option<T>> {
+        let conn = self.conn.lock();
+        let Some(data) = select_one(&conn, &self.select_one, id)? else {
+            return Ok(None);
+        };
+        let current: T = decode(&data)?;
+        let mut record = current.clone();
+        if !f(&mut record) {
+            return Ok(Some(current));
+        }
+        self.check_unique(&conn, &record)?;
+        conn.prepare_cached(&self.update)?
+            .execute(params![id, encode(&record)?])
+            .map_err(constraint::<T>)?;
+        Ok(Some(record))
+    }
+
+    fn delete(&self, id: &str) -> StoreResult<bool> {
+        let conn = self.conn.lock();
+        Ok(conn.prepare_cached(&self.delete)?.execute([id])? > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db() -> String {
+        let name = format!("todos-test-{}.db", uuid::Uuid::new_v4());
+        std::env::temp_dir()
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn round_trips_records_across_reopen() {
+        let path = temp_db();
+        let first = Todo::new("first".into());
+        let second = Todo::new("second".into());
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            store.todos().insert(first.clone()).unwrap();
+            store.todos().insert(second.clone()).unwrap();
+            store.tags().insert(Tag::new("work".into(), None)).unwrap();
+            let updated = store
+                .todos()
+                .update(&first.id, &mut |t| t.completed = true)
+                .unwrap();
+            assert!(updated.is_some_and(|t| t.completed));
+            assert!(store.todos().delete(&second.id).unwrap());
+            assert!(!store.todos().delete(&second.id).unwrap());
+        }
+
+        let store = SqliteStore::open(&path).unwrap();
+        let todos = store.todos().list().unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].id, first.id);
+        assert!(todos[0].completed);
+        assert_eq!(store.tags().list().unwrap()[0].name, "work");
+        assert!(store.todos().get(&second.id).unwrap().is_none());
+        assert!(
+            store
+                .todos()
+                .update("missing", &mut |_| {})
+                .unwrap()
+                .is_none()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn keeps_insertion_order() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let titles = ["c", "a", "b"];
+        for title in titles {
+            store.todos().insert(Todo::new(title.into())).unwrap();
+        }
+        let listed: Vec<String> = store
+            .todos()
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.title)
+            .collect();
+        assert_eq!(listed, titles);
+    }
+
+    #[test]
+    fn rejects_duplicate_tag_names_ignoring_case() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let work = Tag::new("Work".into(), None);
+        let home = Tag::new("home".into(), None);
+        store.tags().insert(work.clone()).unwrap();
+        store.tags().insert(home.clone()).unwrap();
+
+        let err = store.tags().insert(Tag::new("WORK".into(), None));
+        assert!(matches!(
+            err,
+            Err(StoreError::Conflict { field: "name", .. })
+        ));
+        let err = store
+            .tags()
+            .update(&home.id, &mut |t| t.name = "work".into());
+        assert!(matches!(
+            err,
+            Err(StoreError::Conflict { field: "name", .. })
+        ));
+        assert_eq!(store.tags().get(&home.id).unwrap().unwrap().name, "home");
+
+        // Renaming a tag to a different case of its own name is fine.
+        let renamed = store
+            .tags()
+            .update(&work.id, &mut |t| t.name = "work".into());
+        assert_eq!(renamed.unwrap().unwrap().name, "work");
+    }
+
+    #[test]
+    fn unique_index_backs_up_the_check() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        store.tags().insert(Tag::new("Work".into(), None)).unwrap();
+        let conn = store.tags.conn.lock();
+        let err = conn
+            .execute(
+                "INSERT INTO tags (id, data) VALUES ('x', '{\"id\":\"x\",\"name\":\"work\"}')",
+                [],
+            )
+            .unwrap_err();
+        assert!(matches!(
+            constraint::<Tag>(err),
+            StoreError::Conflict { .. }
+        ));
+    }
+}
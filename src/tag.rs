@@ -0,0 +1,61 @@
+use juniper::graphql_object;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Context;
+use crate::error::{AppError, AppResult};
+use crate::store::StoreError;
+use crate::todo::Todo;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+impl Tag {
+    pub fn new(name: String, color: Option<String>) -> Self {
+        Tag {
+            id: Uuid::new_v4().to_string(),
+            name,
+            color,
+        }
+    }
+}
+
+/// Reports a name the store rejected as a duplicate (tag names are unique
+/// ignoring case) as a validation error on `name`.
+pub fn name_conflict(e: StoreError) -> AppError {
+    match e {
+        StoreError::Conflict { .. } => {
+            AppError::invalid("name", "a tag with this name already exists")
+        }
+        e => e.into(),
+    }
+}
+
+#[graphql_object(context = Context)]
+impl Tag {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Unique across all tags, ignoring case.
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// `#rrggbb`, if set.
+    fn color(&self) -> Option<&str> {
+        self.color.as_deref()
+    }
+
+    /// Todos carrying this tag, in store order.
+    fn todos(&self, context: &Context) -> AppResult<Vec<Todo>> {
+        let mut todos = context.store.todos().list()?;
+        todos.retain(|t| t.tag_ids.contains(&self.id));
+        Ok(todos)
+    }
+}
@@ -0,0 +1,333 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use juniper::{GraphQLEnum, graphql_object};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Context;
+use crate::checklist::ChecklistItem;
+use crate::dependency;
+use crate::error::{AppError, AppResult};
+use crate::hierarchy;
+use crate::list::{self, TodoList};
+use crate::markdown;
+use crate::recurrence::Recurrence;
+use crate::tag::Tag;
+
+#[derive(
+    GraphQLEnum, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+    Urgent,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Todo {
+    pub id: String,
+    pub title: String,
+    /// Markdown.
+    #[serde(default)]
+    pub notes: Option<String>,
+    pub completed: bool,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub tag_ids: Vec<String>,
+    /// Todos saved before lists existed belong to the inbox.
+    #[serde(default = "list::inbox_id")]
+    pub list_id: String,
+    /// The todo this is a subtask of.
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Ids of the todos that must be completed before this one can start.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// Fractional index for manual ordering, see [`crate::position`].
+    #[serde(default)]
+    pub position: String,
+    /// Kept in insertion order; sort by `position` for display.
+    #[serde(default)]
+    pub checklist: Vec<ChecklistItem>,
+}
+
+impl Todo {
+    pub fn new(title: String) -> Self {
+        let now = Utc::now();
+        Todo {
+            id: Uuid::new_v4().to_string(),
+            title,
+            notes: None,
+            completed: false,
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+            due_at: None,
+            priority: Priority::None,
+            tag_ids: Vec::new(),
+            list_id: list::inbox_id(),
+            parent_id: None,
+            depends_on: Vec::new(),
+            recurrence: None,
+            position: String::new(),
+            checklist: Vec::new(),
+        }
+    }
+
+    /// Completing a recurring todo hands its recurrence over to the next
+    /// occurrence, which is returned for the caller to store. Nothing is
+    /// returned once the series has ended.
+    pub fn set_completed(&mut self, completed: bool, now: DateTime<Utc>) -> Option<Todo> {
+        if completed == self.completed {
+            return None;
+        }
+        self.completed = completed;
+        self.completed_at = completed.then_some(now);
+        if !completed {
+            return None;
+        }
+        let recurrence = self.recurrence.take()?;
+        let due_at = recurrence.next_after(self.due_at?)?;
+        let mut next = Todo::new(self.title.clone());
+        next.notes = self.notes.clone();
+        next.checklist = self.checklist.clone();
+        next.checklist.iter_mut().for_each(|i| i.checked = false);
+        next.due_at = Some(due_at);
+        next.priority = self.priority;
+        next.tag_ids = self.tag_ids.clone();
+        next.list_id = self.list_id.clone();
+        next.parent_id = self.parent_id.clone();
+        next.recurrence = Some(recurrence);
+        Some(next)
+    }
+
+    pub fn overdue_at(&self, now: DateTime<Utc>) -> bool {
+        !self.completed && self.due_at.is_some_and(|due| due < now)
+    }
+}
+
+pub fn resolve_time_zone(name: &str) -> AppResult<Tz> {
+    name.parse()
+        .map_err(|_| AppError::invalid("timeZone", format!("unknown time zone {name:?}")))
+}
+
+#[graphql_object(context = Context)]
+impl Todo {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Markdown source of the notes.
+    fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    /// `notes` rendered to sanitized HTML, so every client shows the same
+    /// formatting. Raw HTML in the notes is escaped, and only http(s) and
+    /// mailto links are kept.
+    fn notes_html(&self) -> Option<String> {
+        self.notes.as_deref().map(markdown::to_html)
+    }
+
+    fn completed(&self) -> bool {
+        self.completed
+    }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    /// When the todo was last marked completed; cleared when reopened.
+    fn completed_at(&self) -> Option<DateTime<Utc>> {
+        self.completed_at
+    }
+
+    fn due_at(&self) -> Option<DateTime<Utc>> {
+        self.due_at
+    }
+
+    fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Manual sort key set by `reorderTodo`; sort by `POSITION` to use it.
+    /// Compare positions as plain strings.
+    fn position(&self) -> &str {
+        &self.position
+    }
+
+    fn list(&self, context: &Context) -> AppResult<TodoList> {
+        context
+            .store
+            .lists()
+            .get(&self.list_id)?
+            .ok_or_else(|| AppError::not_found("list", &self.list_id))
+    }
+
+    fn parent(&self, context: &Context) -> AppResult<Option<Todo>> {
+        match &self.parent_id {
+            Some(id) => Ok(context.store.todos().get(id)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Direct subtasks, in store order.
+    fn children(&self, context: &Context) -> AppResult<Vec<Todo>> {
+        let todos = context.store.todos().list()?;
+        Ok(hierarchy::children(&todos, &self.id).cloned().collect())
+    }
+
+    /// Fraction of subtasks at any depth that are completed, from 0 to 1;
+    /// `null` if the todo has no subtasks.
+    fn progress(&self, context: &Context) -> AppResult<Option<f64>> {
+        let todos = context.store.todos().list()?;
+        let descendants = hierarchy::descendants(&todos, &self.id);
+        if descendants.is_empty() {
+            return Ok(None);
+        }
+        let done = descendants.iter().filter(|t| t.completed).count();
+        Ok(Some(done as f64 / descendants.len() as f64))
+    }
+
+    /// Todos this one depends on, in the order the dependencies were added.
+    fn blocked_by(&self, context: &Context) -> AppResult<Vec<Todo>> {
+        let todos = context.store.todos().list()?;
+        Ok(dependency::blocked_by(&todos, self)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    /// Todos that depend on this one.
+    fn blocks(&self, context: &Context) -> AppResult<Vec<Todo>> {
+        let todos = context.store.todos().list()?;
+        Ok(dependency::blocks(&todos, &self.id)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    /// Whether any todo this one depends on is still open.
+    fn is_blocked(&self, context: &Context) -> AppResult<bool> {
+        let todos = context.store.todos().list()?;
+        Ok(dependency::blocked_by(&todos, self)
+            .iter()
+            .any(|t| !t.completed))
+    }
+
+    /// Checklist items in their manual order.
+    fn checklist(&self) -> Vec<ChecklistItem> {
+        let mut items = self.checklist.clone();
+        items.sort_by(|a, b| a.position.cmp(&b.position));
+        items
+    }
+
+    /// Fraction of checklist items that are checked, from 0 to 1; `null` if
+    /// the checklist is empty.
+    fn checklist_progress(&self) -> Option<f64> {
+        if self.checklist.is_empty() {
+            return None;
+        }
+        let checked = self.checklist.iter().filter(|i| i.checked).count();
+        Some(checked as f64 / self.checklist.len() as f64)
+    }
+
+    /// Tags in the order they were added.
+    fn tags(&self, context: &Context) -> AppResult<Vec<Tag>> {
+        let tags = context.store.tags().list()?;
+        Ok(self
+            .tag_ids
+            .iter()
+            .filter_map(|id| tags.iter().find(|t| &t.id == id).cloned())
+            .collect())
+    }
+
+    /// The `RRULE` the todo repeats by, e.g. `FREQ=WEEKLY;BYDAY=MO,WE`.
+    /// Completing the todo creates the next occurrence, which takes over the
+    /// rule.
+    fn recurrence(&self) -> Option<String> {
+        self.recurrence.as_ref().map(|r| r.rule.to_string())
+    }
+
+    /// IANA zone the recurrence is evaluated in.
+    fn recurrence_time_zone(&self) -> Option<&str> {
+        self.recurrence.as_ref().map(|r| r.time_zone.name())
+    }
+
+    /// Due dates of up to `limit` upcoming occurrences after this one.
+    fn next_occurrences(
+        &self,
+        #[graphql(default = 5)] limit: i32,
+    ) -> AppResult<Vec<DateTime<Utc>>> {
+        if !(0..=100).contains(&limit) {
+            return Err(AppError::invalid("limit", "must be between 0 and 100"));
+        }
+        let (Some(recurrence), Some(due_at)) = (&self.recurrence, self.due_at) else {
+            return Ok(Vec::new());
+        };
+        Ok(recurrence
+            .occurrences()
+            .skip_while(|&at| at <= due_at)
+            .take(limit as usize)
+            .collect())
+    }
+
+    /// Whether the todo is still open and its due time has passed.
+    fn is_overdue(&self) -> bool {
+        self.overdue_at(Utc::now())
+    }
+
+    /// Calendar days from today until the due date, both taken in the IANA
+    /// `timeZone`: `0` when due today, negative once past due.
+    fn due_in(
+        &self,
+        #[graphql(default = "UTC".to_owned())] time_zone: String,
+    ) -> AppResult<Option<i32>> {
+        let Some(due_at) = self.due_at else {
+            return Ok(None);
+        };
+        let tz = resolve_time_zone(&time_zone)?;
+        let today = Utc::now().with_timezone(&tz).date_naive();
+        let due = due_at.with_timezone(&tz).date_naive();
+        Ok(Some((due - today).num_days() as i32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_iana_zones_and_rejects_unknown_names() {
+        assert_eq!(resolve_time_zone("UTC").unwrap(), Tz::UTC);
+        assert_eq!(
+            resolve_time_zone("Europe/Berlin").unwrap(),
+            Tz::Europe__Berlin
+        );
+        assert!(resolve_time_zone("Mars/Olympus_Mons").is_err());
+        assert!(resolve_time_zone("").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! Input validation shared by all mutations. A [`Validator`] normalizes each
+//! field and collects every violation, so a client gets all problems with a
+//! request in one error instead of fixing them one round trip at a time.
+
+use chrono_tz::Tz;
+
+use crate::error::{AppError, AppResult};
+use crate::recurrence::Rule;
+
+/// Configurable input limits, counted in characters.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub title_max_len: usize,
+    pub tag_name_max_len: usize,
+    pub description_max_len: usize,
+    pub notes_max_len: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            title_max_len: 200,
+            tag_name_max_len: 50,
+            description_max_len: 2000,
+            notes_max_len: 10_000,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Violation {
+    /// Path of the offending argument, e.g. `input.title`.
+    pub field: String,
+    pub message: String,
+}
+
+pub struct Validator<'a> {
+    limits: &'a Limits,
+    violations: Vec<Violation>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(limits: &'a Limits) -> Self {
+        Self {
+            limits,
+            violations: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.violations.push(Violation {
+            field: field.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    /// Trims a todo title and checks it is non-empty, within
+    /// [`Limits::title_max_len`] and free of control characters.
+    pub fn title(&mut self, field: &str, value: &str) -> String {
+        self.line(field, value, self.limits.title_max_len)
+    }
+
+    /// Like [`Validator::title`], bounded by [`Limits::tag_name_max_len`].
+    pub fn tag_name(&mut self, field: &str, value: &str) -> String {
+        self.line(field, value, self.limits.tag_name_max_len)
+    }
+
+    /// Trims multi-line free text, bounded by [`Limits::description_max_len`].
+    /// Blank text becomes `None`.
+    pub fn description(&mut self, field: &str, value: &str) -> Option<String> {
+        self.text(field, value, self.limits.description_max_len)
+    }
+
+    /// Like [`Validator::description`] for Markdown notes, bounded by
+    /// [`Limits::notes_max_len`].
+    pub fn notes(&mut self, field: &str, value: &str) -> Option<String> {
+        self.text(field, value, self.limits.notes_max_len)
+    }
+
+    /// Checks a color is given as `#RRGGBB` and lowercases it.
+    pub fn color(&mut self, field: &str, value: &str) -> String {
+        let valid = value
+            .strip_prefix('#')
+            .is_some_and(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+        if !valid {
+            self.add(field, "must be a hex color like #1e90ff");
+        }
+        value.to_ascii_lowercase()
+    }
+
+    /// Parses an iCalendar `RRULE`, reporting why it was rejected.
+    pub fn rule(&mut self, field: &str, value: &str) -> Option<Rule> {
+        value
+            .parse()
+            .inspect_err(|e: &String| self.add(field, format!("invalid RRULE: {e}")))
+            .ok()
+    }
+
+    /// An IANA time zone name such as `Europe/Berlin`.
+    pub fn time_zone(&mut self, field: &str, value: &str) -> Option<Tz> {
+        value
+            .parse()
+            .inspect_err(|_| self.add(field, format!("unknown time zone {value:?}")))
+            .ok()
+    }
+
+    /// Trims a single line of text and checks it is non-empty, at most `max`
+    /// characters and free of control characters.
+    fn line(&mut self, field: &str, value: &str, max: usize) -> String {
+        let value = value.trim();
+        if value.is_empty() {
+            self.add(field, "must not be empty");
+        }
+        self.max_len(field, value, max);
+        if value.chars().any(char::is_control) {
+            self.add(field, "must not contain control characters");
+        }
+        value.to_owned()
+    }
+
+    /// Trims multi-line text and checks it is at most `max` characters. Line
+    /// breaks and tabs are the only control characters allowed.
+    fn text(&mut self, field: &str, value: &str, max: usize) -> Option<String> {
+        let value = value.trim();
+        self.max_len(field, value, max);
+        if value
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+        {
+            self.add(field, "must not contain control characters");
+        }
+        (!value.is_empty()).then(|| value.to_owned())
+    }
+
+    fn max_len(&mut self, field: &str, value: &str, max: usize) {
+        if value.chars().count() > max {
+            self.add(field, format!("must be at most {max} characters"));
+        }
+    }
+
+    /// Fails with every collected violation, if there were any.
+    pub fn finish(self) -> AppResult<()> {
+        if self.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Invalid(self.violations))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violations(v: Validator) -> Vec<(String, String)> {
+        match v.finish() {
+            Ok(()) => Vec::new(),
+            Err(AppError::Invalid(violations)) => violations
+                .into_iter()
+                .map(|v| (v.field, v.message))
+                .collect(),
+            Err(e) => panic!("unexpected error {e}"),
+        }
+    }
+
+    #[test]
+    fn trims_and_accepts_valid_input() {
+        let limits = Limits::default();
+        let mut v = Validator::new(&limits);
+        assert_eq!(v.title("title", "  Buy milk \n"), "Buy milk");
+        assert_eq!(v.tag_name("name", " work "), "work");
+        assert_eq!(v.color("color", "#1E90FF"), "#1e90ff");
+        assert_eq!(
+            v.notes("notes", "\n- one\n\t- two\r\n").as_deref(),
+            Some("- one\n\t- two")
+        );
+        assert_eq!(v.description("description", "   "), None);
+        assert!(v.rule("recurrence", "FREQ=DAILY").is_some());
+        assert_eq!(
+            v.time_zone("timeZone", "Europe/Berlin"),
+            Some(Tz::Europe__Berlin)
+        );
+        assert!(violations(v).is_empty());
+    }
+
+    #[test]
+    fn collects_every_violation() {
+        let limits = Limits::default();
+        let mut v = Validator::new(&limits);
+        v.title("input.title", "   ");
+        v.title("other", "tab\there");
+        v.color("color", "blue");
+        v.rule("recurrence", "FREQ=HOURLY");
+        v.time_zone("timeZone", "Nowhere/Special");
+        v.notes("notes", "bell \u{7}");
+        let found = violations(v);
+        let fields: Vec<&str> = found.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(
+            fields,
+            [
+                "input.title",
+                "other",
+                "color",
+                "recurrence",
+                "timeZone",
+                "notes"
+            ]
+        );
+        assert_eq!(found[0].1, "must not be empty");
+        assert_eq!(found[1].1, "must not contain control characters");
+        assert!(found[3].1.starts_with("invalid RRULE: "));
+    }
+
+    #[test]
+    fn counts_length_in_characters() {
+        let limits = Limits {
+            title_max_len: 3,
+            tag_name_max_len: 2,
+            description_max_len: 4,
+            notes_max_len: 5,
+        };
+        let mut v = Validator::new(&limits);
+        v.title("ok", "äöü");
+        v.title("title", "abcd");
+        v.tag_name("name", "abc");
+        v.description("description", "abcde");
+        v.notes("notes", "abcdef");
+        let found = violations(v);
+        let fields: Vec<&str> = found.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(fields, ["title", "name", "description", "notes"]);
+        assert_eq!(found[0].1, "must be at most 3 characters");
+    }
+}